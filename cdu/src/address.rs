@@ -0,0 +1,80 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+
+/// Ranges which must not be published in public DNS, with their names
+static NON_PUBLIC_RANGES: Lazy<Vec<(IpNet, &str)>> = Lazy::new(|| {
+    [
+        ("0.0.0.0/8", "this network"),
+        ("10.0.0.0/8", "private"),
+        ("100.64.0.0/10", "CGNAT"),
+        ("127.0.0.0/8", "loopback"),
+        ("169.254.0.0/16", "link-local"),
+        ("172.16.0.0/12", "private"),
+        ("192.0.0.0/24", "IETF protocol assignments"),
+        ("192.0.2.0/24", "documentation"),
+        ("192.168.0.0/16", "private"),
+        ("198.18.0.0/15", "benchmarking"),
+        ("198.51.100.0/24", "documentation"),
+        ("203.0.113.0/24", "documentation"),
+        ("224.0.0.0/4", "multicast"),
+        ("240.0.0.0/4", "reserved"),
+        ("::/128", "unspecified"),
+        ("::1/128", "loopback"),
+        ("::ffff:0:0/96", "IPv4-mapped"),
+        ("64:ff9b::/96", "NAT64"),
+        ("100::/64", "discard-only"),
+        ("2001::/23", "IETF protocol assignments"),
+        ("2001:db8::/32", "documentation"),
+        ("2002::/16", "6to4"),
+        ("fc00::/7", "unique local"),
+        ("fe80::/10", "link-local"),
+        ("ff00::/8", "multicast"),
+    ]
+    .iter()
+    .map(|(net, name)| (net.parse().expect("invalid built-in range"), *name))
+    .collect()
+});
+
+/// Name of the range if IP address must not be published in public DNS
+pub(crate) fn non_public_range(ip: &IpAddr) -> Option<&'static str> {
+    NON_PUBLIC_RANGES
+        .iter()
+        .find_map(|(net, name)| net.contains(ip).then_some(*name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_non_public_range() {
+        let cases = [
+            ("1.1.1.1", None),
+            ("10.1.2.3", Some("private")),
+            ("100.64.0.1", Some("CGNAT")),
+            ("100.128.0.1", None),
+            ("127.0.0.1", Some("loopback")),
+            ("169.254.1.1", Some("link-local")),
+            ("172.31.255.255", Some("private")),
+            ("172.32.0.1", None),
+            ("192.168.1.1", Some("private")),
+            ("198.51.100.7", Some("documentation")),
+            ("2606:4700:4700::1111", None),
+            ("::1", Some("loopback")),
+            ("2001:db8::1", Some("documentation")),
+            ("fd00::1", Some("unique local")),
+            ("fe80::1", Some("link-local")),
+            ("::ffff:10.0.0.1", Some("IPv4-mapped")),
+            ("64:ff9b::a00:1", Some("NAT64")),
+            ("2001:0:4136:e378::1", Some("IETF protocol assignments")),
+            ("2001:200::1", None),
+            ("2002:c000:204::1", Some("6to4")),
+        ];
+        for (ip, expected) in cases {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(expected, non_public_range(&ip), "{ip}");
+        }
+    }
+}
@@ -0,0 +1,598 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context as _};
+use cdu::{
+    parse_ip_source, parse_record_selector, ApiClient, Cdu, CloudflareIpList, CloudflareProvider,
+    CreateOptions, Credentials, DnsProvider, IpNet, IpSource, QuorumIpSource, RecordOptions,
+    RecordSelector, RecordType, Rfc2136Provider,
+};
+use serde::{Deserialize, Deserializer};
+
+/// Configuration file with multiple zones
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Zones to update
+    pub zones: Vec<ZoneConfig>,
+}
+
+impl Config {
+    /// Load configuration from TOML file
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read config file: {}", path.display()))?;
+        let config: Config = toml::from_str(&content)
+            .with_context(|| format!("invalid config file: {}", path.display()))?;
+        if config.zones.is_empty() {
+            bail!("no zone in config file: {}", path.display());
+        }
+        Ok(config)
+    }
+}
+
+/// Read secret from file e.g. a Docker or Kubernetes secret, without surrounding whitespace
+pub fn read_secret(path: &Path) -> anyhow::Result<String> {
+    let secret = fs::read_to_string(path)
+        .with_context(|| format!("cannot read secret file: {}", path.display()))?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        bail!("secret file is empty: {}", path.display());
+    }
+    Ok(secret.to_string())
+}
+
+/// Cloudflare credentials, either an API token or a global API key with email of account,
+/// each given directly or in a file. `None` if neither is given
+pub fn cloudflare_credentials(
+    token: Option<&str>,
+    token_file: Option<&Path>,
+    email: Option<&str>,
+    api_key: Option<&str>,
+    api_key_file: Option<&Path>,
+) -> anyhow::Result<Option<Credentials>> {
+    if email.is_some() && (token.is_some() || token_file.is_some()) {
+        bail!("token conflicts with global API key");
+    }
+    match (token, token_file) {
+        (Some(_), Some(_)) => bail!("token conflicts with token file"),
+        (Some(token), None) => return Ok(Some(Credentials::from(token))),
+        (None, Some(path)) => return Ok(Some(Credentials::Token(read_secret(path)?))),
+        (None, None) => {}
+    }
+    let email = match email {
+        Some(email) => email,
+        None if api_key.is_some() || api_key_file.is_some() => {
+            bail!("email is required with global API key")
+        }
+        None => return Ok(None),
+    };
+    let api_key = match (api_key, api_key_file) {
+        (Some(_), Some(_)) => bail!("global API key conflicts with global API key file"),
+        (Some(api_key), None) => api_key.to_string(),
+        (None, Some(path)) => read_secret(path)?,
+        (None, None) => bail!("global API key is required with email"),
+    };
+    Ok(Some(Credentials::global_key(email, api_key)))
+}
+
+fn default_record_types() -> Vec<RecordType> {
+    vec![RecordType::A]
+}
+
+fn default_ip_sources() -> Vec<Arc<dyn IpSource>> {
+    vec![Arc::new(cdu::PublicIpSource)]
+}
+
+fn deserialize_ip_sources<'de, D>(deserializer: D) -> Result<Vec<Arc<dyn IpSource>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let specs = Vec::<String>::deserialize(deserializer)?;
+    specs
+        .iter()
+        .map(|s| parse_ip_source(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn default_tsig_algorithm() -> String {
+    "hmac-sha256".to_string()
+}
+
+/// Settings of authoritative DNS server accepting RFC 2136 dynamic updates signed with TSIG
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rfc2136Config {
+    /// Address of primary server e.g. `192.0.2.1:53`
+    pub server: SocketAddr,
+    /// Name of TSIG key
+    pub key_name: String,
+    /// TSIG algorithm, one of `hmac-sha256`, `hmac-sha384` and `hmac-sha512`
+    #[serde(default = "default_tsig_algorithm")]
+    pub algorithm: String,
+    /// TSIG secret encoded in base64
+    pub secret: String,
+    /// Time to live of DNS records updated without explicit one
+    pub ttl: Option<u32>,
+}
+
+impl Rfc2136Config {
+    fn build(&self) -> anyhow::Result<Rfc2136Provider> {
+        let provider =
+            Rfc2136Provider::new(self.server, &self.key_name, &self.algorithm, &self.secret)?;
+        Ok(match self.ttl {
+            Some(ttl) => provider.with_ttl(ttl),
+            None => provider,
+        })
+    }
+}
+
+/// Cloudflare IP list of an account, kept holding the IP addresses published in the zone
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IpListConfig {
+    /// Cloudflare account identifier
+    pub account_id: String,
+    /// Name of IP list
+    pub name: String,
+    /// Comment of items added, which are replaced on change, defaults to `cdu`
+    pub comment: Option<String>,
+}
+
+impl IpListConfig {
+    fn build(&self, credentials: &Credentials, client: &ApiClient) -> CloudflareIpList {
+        let list = CloudflareIpList::new(
+            credentials.clone(),
+            self.account_id.as_str(),
+            self.name.as_str(),
+        )
+        .with_client(client.clone());
+        match &self.comment {
+            Some(comment) => list.with_comment(comment.as_str()),
+            None => list,
+        }
+    }
+}
+
+/// Settings of one zone
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneConfig {
+    /// Cloudflare token, required unless DNS records are updated with RFC 2136
+    /// or with global API key
+    pub token: Option<String>,
+    /// File holding Cloudflare token, in place of `token`
+    pub token_file: Option<PathBuf>,
+    /// Email of Cloudflare account, to authenticate with global API key instead of token
+    pub email: Option<String>,
+    /// Cloudflare global API key, requires `email`
+    pub api_key: Option<String>,
+    /// File holding Cloudflare global API key, in place of `api_key`
+    pub api_key_file: Option<PathBuf>,
+    /// Update DNS records on authoritative DNS server with RFC 2136 instead of Cloudflare
+    pub rfc2136: Option<Rfc2136Config>,
+    /// Cloudflare zone name
+    pub zone: String,
+    /// Cloudflare records, record type can be specified per record e.g. a.x.com:AAAA,
+    /// or selected with glob e.g. `glob:*.x.com` or regular expression e.g. `regex:^a\.`
+    pub records: Vec<String>,
+    /// Record types to update
+    #[serde(default = "default_record_types")]
+    pub record_types: Vec<RecordType>,
+    /// Sources of IP address e.g. `public`, `url=<URL>`, `interface=<NAME>` or `command=<COMMAND>`
+    #[serde(
+        default = "default_ip_sources",
+        deserialize_with = "deserialize_ip_sources"
+    )]
+    pub ip_sources: Vec<Arc<dyn IpSource>>,
+    /// Number of IP address sources required to agree, defaults to majority of them
+    pub quorum: Option<usize>,
+    /// Networks allowed to publish though private, CGNAT or reserved
+    #[serde(default)]
+    pub allowed_networks: Vec<IpNet>,
+    /// Time to live of all DNS records to set on update
+    pub ttl: Option<u32>,
+    /// Whether all DNS records are proxied by Cloudflare to set on update
+    pub proxied: Option<bool>,
+    /// Settings per record to set on update
+    #[serde(default)]
+    pub record_options: HashMap<String, RecordOptions>,
+    /// Create DNS records which do not exist with the settings
+    pub create_missing: Option<CreateOptions>,
+    /// File to persist last published IP addresses, zone and record identifiers
+    pub state_file: Option<PathBuf>,
+    /// Cloudflare IP lists to keep holding the published IP addresses
+    #[serde(default)]
+    pub ip_lists: Vec<IpListConfig>,
+}
+
+/// Build IP address source requiring a quorum if there are multiple sources
+pub fn build_ip_source(
+    sources: &[Arc<dyn IpSource>],
+    quorum: Option<usize>,
+) -> anyhow::Result<Arc<dyn IpSource>> {
+    let len = sources.len();
+    if len == 1 && quorum.map_or(true, |q| q == 1) {
+        return Ok(sources[0].clone());
+    }
+    let quorum = quorum.unwrap_or(len / 2 + 1);
+    Ok(Arc::new(QuorumIpSource::new(sources.to_vec(), quorum)?))
+}
+
+impl ZoneConfig {
+    /// Cloudflare credentials of the zone, `None` if neither token nor global API key is given
+    pub fn credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        cloudflare_credentials(
+            self.token.as_deref(),
+            self.token_file.as_deref(),
+            self.email.as_deref(),
+            self.api_key.as_deref(),
+            self.api_key_file.as_deref(),
+        )
+        .with_context(|| format!("invalid Cloudflare credentials for zone {}", self.zone))
+    }
+
+    /// Build a [`Cdu`] of the zone, calling Cloudflare API with `client`
+    pub fn build(&self, identifier_ttl: Duration, client: &ApiClient) -> anyhow::Result<Cdu<'_>> {
+        for record in &self.records {
+            let (selector, _) = parse_record_selector(record)
+                .with_context(|| format!("invalid record {record} for zone {}", self.zone))?;
+            // RFC 2136 servers cannot list DNS records to expand selectors against
+            if self.rfc2136.is_some() && !matches!(selector, RecordSelector::Name(_)) {
+                bail!(
+                    "record selector {record} is not supported by RFC 2136 for zone {}, name DNS records exactly",
+                    self.zone
+                );
+            }
+        }
+        let credentials = self.credentials()?;
+        let provider: Arc<dyn DnsProvider> = match (&credentials, &self.rfc2136) {
+            (Some(credentials), None) => {
+                Arc::new(CloudflareProvider::new(credentials.clone()).with_client(client.clone()))
+            }
+            (None, Some(rfc2136)) => Arc::new(rfc2136.build()?),
+            (None, None) => bail!("token is required for zone {}", self.zone),
+            (Some(_), Some(_)) => bail!("token conflicts with rfc2136 for zone {}", self.zone),
+        };
+        let mut cdu = Cdu::from_provider(provider, self.zone.as_str(), &self.records)
+            .with_record_types(&self.record_types)
+            .with_create_missing(self.create_missing)
+            .with_identifier_ttl(identifier_ttl)
+            .with_ip_source(build_ip_source(&self.ip_sources, self.quorum)?)
+            .with_allowed_networks(&self.allowed_networks)
+            .with_record_options(RecordOptions {
+                ttl: self.ttl,
+                proxied: self.proxied,
+            });
+        for (record_name, options) in &self.record_options {
+            cdu = cdu.with_options_for(record_name, *options);
+        }
+        for ip_list in &self.ip_lists {
+            let credentials = match &credentials {
+                Some(credentials) => credentials,
+                None => bail!("token is required for IP lists of zone {}", self.zone),
+            };
+            cdu = cdu.with_ip_list(Arc::new(ip_list.build(credentials, client)));
+        }
+        if let Some(state_file) = &self.state_file {
+            cdu = cdu.with_state_file(state_file)?;
+        }
+        Ok(cdu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cdu.toml");
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+token = "token1"
+zone = "x.com"
+records = ["a.x.com", "b.x.com:AAAA"]
+
+[[zones]]
+token = "token2"
+zone = "y.com"
+records = ["a.y.com"]
+record_types = ["A", "AAAA"]
+ip_sources = ["public", "url=https://api.ipify.org", "interface=ppp0"]
+quorum = 2
+allowed_networks = ["10.0.0.0/8"]
+ttl = 300
+proxied = true
+state_file = "/var/lib/cdu/y.com.json"
+
+[[zones.ip_lists]]
+account_id = "account"
+name = "office"
+comment = "cdu y.com"
+
+[zones.record_options."a.y.com"]
+ttl = 60
+
+[zones.create_missing]
+proxied = true
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(2, config.zones.len());
+
+        let x = &config.zones[0];
+        assert_eq!(Some("token1"), x.token.as_deref());
+        assert_eq!(vec!["a.x.com", "b.x.com:AAAA"], x.records);
+        assert_eq!(vec![RecordType::A], x.record_types);
+        assert_eq!(1, x.ip_sources.len());
+        assert!(x.create_missing.is_none());
+        assert!(x
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .is_ok());
+
+        let y = &config.zones[1];
+        assert_eq!(vec![RecordType::A, RecordType::AAAA], y.record_types);
+        assert_eq!(3, y.ip_sources.len());
+        assert_eq!(Some(2), y.quorum);
+        assert_eq!(
+            vec!["10.0.0.0/8".parse::<IpNet>().unwrap()],
+            y.allowed_networks
+        );
+        assert_eq!(
+            RecordOptions {
+                ttl: Some(60),
+                proxied: None
+            },
+            y.record_options["a.y.com"]
+        );
+        let create_missing = y.create_missing.unwrap();
+        assert_eq!(1, create_missing.ttl);
+        assert!(create_missing.proxied);
+        assert_eq!(
+            vec![IpListConfig {
+                account_id: "account".into(),
+                name: "office".into(),
+                comment: Some("cdu y.com".into()),
+            }],
+            y.ip_lists
+        );
+        assert!(x.ip_lists.is_empty());
+    }
+
+    #[test]
+    fn t_load_rfc2136() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cdu.toml");
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+zone = "x.com"
+records = ["a.x.com"]
+
+[zones.rfc2136]
+server = "127.0.0.1:53"
+key_name = "cdu-key"
+secret = "c2VjcmV0"
+ttl = 60
+
+[[zones.ip_lists]]
+account_id = "account"
+name = "office"
+"#,
+        )
+        .unwrap();
+
+        // IP lists require Cloudflare token
+        let config = Config::load(&path).unwrap();
+        let e = config.zones[0]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap_err();
+        assert_eq!(
+            "token is required for IP lists of zone x.com",
+            e.to_string()
+        );
+
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+zone = "x.com"
+records = ["a.x.com"]
+
+[zones.rfc2136]
+server = "127.0.0.1:53"
+key_name = "cdu-key"
+secret = "c2VjcmV0"
+ttl = 60
+"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        let x = &config.zones[0];
+        assert!(x.token.is_none());
+        let rfc2136 = x.rfc2136.as_ref().unwrap();
+        assert_eq!("hmac-sha256", rfc2136.algorithm);
+        assert_eq!(Some(60), rfc2136.ttl);
+        let cdu = x
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap();
+        assert!(format!("{cdu:?}").contains("Rfc2136Provider"));
+
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+zone = "x.com"
+records = ["a.x.com", "glob:*.home.x.com"]
+
+[zones.rfc2136]
+server = "127.0.0.1:53"
+key_name = "cdu-key"
+secret = "c2VjcmV0"
+"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        let e = config.zones[0]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap_err();
+        assert_eq!(
+            "record selector glob:*.home.x.com is not supported by RFC 2136 for zone x.com, name DNS records exactly",
+            e.to_string()
+        );
+
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+zone = "x.com"
+records = ["a.x.com"]
+"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert!(config.zones[0]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .is_err());
+    }
+
+    #[test]
+    fn t_build_invalid_record() {
+        let config: Config = toml::from_str(
+            r#"
+[[zones]]
+token = "token"
+zone = "x.com"
+records = ["regex:("]
+"#,
+        )
+        .unwrap();
+        let e = config.zones[0]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap_err();
+        assert_eq!("invalid record regex:( for zone x.com", e.to_string());
+    }
+
+    #[test]
+    fn t_load_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cdu.toml");
+
+        fs::write(&path, "zones = []").unwrap();
+        assert!(Config::load(&path).is_err());
+
+        fs::write(
+            &path,
+            r#"
+[[zones]]
+token = "token"
+zone = "x.com"
+records = ["a.x.com"]
+ip_sources = ["interface"]
+"#,
+        )
+        .unwrap();
+        assert!(Config::load(&path).is_err());
+
+        assert!(Config::load(dir.path().join("missing.toml")).is_err());
+    }
+
+    #[test]
+    fn t_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_file = dir.path().join("token");
+        fs::write(&token_file, "token1\n").unwrap();
+        let key_file = dir.path().join("key");
+        fs::write(&key_file, "key1").unwrap();
+        let empty_file = dir.path().join("empty");
+        fs::write(&empty_file, " \n").unwrap();
+
+        assert_eq!("token1", read_secret(&token_file).unwrap());
+        let e = read_secret(&empty_file).unwrap_err();
+        assert!(e.to_string().starts_with("secret file is empty"));
+        assert!(read_secret(&dir.path().join("missing")).is_err());
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+[[zones]]
+token_file = "{}"
+zone = "x.com"
+records = ["a.x.com"]
+
+[[zones]]
+email = "a@x.com"
+api_key_file = "{}"
+zone = "y.com"
+records = ["a.y.com"]
+
+[[zones]]
+email = "a@x.com"
+zone = "z.com"
+records = ["a.z.com"]
+"#,
+            token_file.display(),
+            key_file.display()
+        ))
+        .unwrap();
+        assert_eq!(
+            Some(Credentials::from("token1")),
+            config.zones[0].credentials().unwrap()
+        );
+        assert_eq!(
+            Some(Credentials::global_key("a@x.com", "key1")),
+            config.zones[1].credentials().unwrap()
+        );
+        assert!(config.zones[1]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .is_ok());
+        let e = config.zones[2]
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap_err();
+        assert_eq!(
+            "invalid Cloudflare credentials for zone z.com: global API key is required with email",
+            format!("{e:#}")
+        );
+
+        let credentials = |token, token_file, email, api_key| {
+            cloudflare_credentials(token, token_file, email, api_key, None)
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(Ok(None), credentials(None, None, None, None));
+        assert_eq!(
+            Ok(Some(Credentials::global_key("a@x.com", "key2"))),
+            credentials(None, None, Some("a@x.com"), Some("key2"))
+        );
+        assert_eq!(
+            Err("token conflicts with token file".into()),
+            credentials(Some("token"), Some(&token_file), None, None)
+        );
+        assert_eq!(
+            Err("token conflicts with global API key".into()),
+            credentials(Some("token"), None, Some("a@x.com"), Some("key2"))
+        );
+        assert_eq!(
+            Err("email is required with global API key".into()),
+            credentials(None, None, None, Some("key2"))
+        );
+    }
+
+    #[test]
+    fn t_build_ip_source() {
+        let sources = default_ip_sources();
+        let source = build_ip_source(&sources, None).unwrap();
+        assert_eq!("PublicIpSource", format!("{source:?}"));
+        assert!(build_ip_source(&sources, Some(2)).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{NonPublicIP, RecordType};
+
+/// Result of DNS record update
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Cause of failure e.g. of [`crate::IpSource`], or of libraries cdu calls
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Error of DNS record update
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Cannot fetch IP address from IP address sources, or sources do not agree
+    #[error("cannot fetch IP address for {record_type} records")]
+    IpDetection {
+        /// Record type whose address family is fetched
+        record_type: RecordType,
+        /// Failure of IP address sources
+        #[source]
+        source: BoxError,
+    },
+    /// IP address sources do not agree on IP address, see [`crate::QuorumIpSource`]
+    #[error(
+        "IP address sources do not reach quorum of {required} for {record_type} records: votes [{}], {failures} failure(s)",
+        votes_of(.votes)
+    )]
+    NoQuorum {
+        /// Record type whose address family is fetched
+        record_type: RecordType,
+        /// IP addresses answered and number of sources answering each of them
+        votes: Vec<(IpAddr, usize)>,
+        /// Number of sources required to agree
+        required: usize,
+        /// Number of sources failing to answer
+        failures: usize,
+    },
+    /// IP address must not be published in public DNS
+    #[error(transparent)]
+    NonPublicIp(#[from] NonPublicIP),
+    /// Record name or selector is invalid
+    #[error("invalid record {record}")]
+    InvalidRecord {
+        /// Record name as given
+        record: String,
+        /// Cause e.g. invalid regular expression
+        #[source]
+        source: BoxError,
+    },
+    /// Record type is neither A nor AAAA
+    #[error("unsupported record type: {record_type}")]
+    UnsupportedRecordType {
+        /// Record type as given
+        record_type: String,
+    },
+    /// IP address source specification is invalid, see [`crate::parse_ip_source`]
+    #[error("invalid IP source: {spec}")]
+    InvalidIpSource {
+        /// Specification as given
+        spec: String,
+    },
+    /// Quorum is not between 1 and number of IP address sources
+    #[error("quorum must be between 1 and {sources}: {quorum}")]
+    InvalidQuorum {
+        /// Quorum as given
+        quorum: usize,
+        /// Number of IP address sources
+        sources: usize,
+    },
+    /// TSIG key of RFC 2136 server is invalid e.g. secret is not base64
+    #[error("invalid TSIG key: {message}")]
+    InvalidTsigKey {
+        /// Reason the key is refused
+        message: String,
+    },
+    /// Zone does not exist, or its cached identifier is stale
+    #[error("zone not found: {zone}")]
+    ZoneNotFound {
+        /// Zone name or identifier
+        zone: String,
+    },
+    /// DNS record does not exist, or its cached identifier is stale
+    #[error("DNS record not found: {name} ({record_type})")]
+    RecordNotFound {
+        /// DNS record name
+        name: String,
+        /// DNS record type
+        record_type: RecordType,
+    },
+    /// IP list does not exist
+    #[error("IP list not found: {name}")]
+    ListNotFound {
+        /// Name of IP list
+        name: String,
+    },
+    /// DNS provider refuses credentials e.g. invalid or expired token
+    #[error("DNS provider refuses credentials: {message}")]
+    Auth {
+        /// Reason given by DNS provider
+        message: String,
+    },
+    /// DNS provider rejects requests beyond its rate limit e.g. HTTP 429
+    #[error("rate limited by DNS provider{}", after(.retry_after))]
+    RateLimited {
+        /// Delay asked by `Retry-After` header, if any
+        retry_after: Option<Duration>,
+    },
+    /// Cannot reach DNS provider e.g. connection refused or timed out
+    #[error("cannot reach DNS provider")]
+    Transport {
+        /// Cause of transport failure
+        #[source]
+        source: BoxError,
+    },
+    /// DNS provider fails request, or responds unexpectedly
+    #[error("DNS provider fails: {message}")]
+    Api {
+        /// HTTP status, if DNS provider is called over HTTP
+        status: Option<u16>,
+        /// Reason given by DNS provider
+        message: String,
+        /// Delay asked by `Retry-After` header, if any
+        retry_after: Option<Duration>,
+    },
+    /// DNS server fails temporarily e.g. SERVFAIL of RFC 2136 server
+    #[error("DNS server fails: {message}")]
+    ServerFailure {
+        /// Reason given by DNS server
+        message: String,
+    },
+    /// Cannot load or save state file
+    #[error(transparent)]
+    State(BoxError),
+}
+
+fn votes_of(votes: &[(IpAddr, usize)]) -> String {
+    votes
+        .iter()
+        .map(|(ip, n)| format!("{ip}={n}"))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn after(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(after) => format!(", retry after {after:?}"),
+        None => String::new(),
+    }
+}
+
+/// How a failure may be retried
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Retry {
+    /// Retry with exponential backoff
+    Backoff,
+    /// Retry no sooner than the delay asked by DNS provider
+    After(Duration),
+}
+
+impl Retry {
+    /// The one which waits longer
+    pub fn max(self, other: Retry) -> Retry {
+        match (self, other) {
+            (Retry::After(a), Retry::After(b)) => Retry::After(a.max(b)),
+            (Retry::After(a), Retry::Backoff) | (Retry::Backoff, Retry::After(a)) => {
+                Retry::After(a)
+            }
+            (Retry::Backoff, Retry::Backoff) => Retry::Backoff,
+        }
+    }
+}
+
+impl Error {
+    /// Whether and how the failure may be retried, `None` if it would fail again.
+    /// Failing to fetch IP address or to reach quorum, rate limits, transport failures
+    /// and server errors are retryable
+    pub fn retry(&self) -> Option<Retry> {
+        let after =
+            |retry_after: Option<Duration>| retry_after.map_or(Retry::Backoff, Retry::After);
+        match self {
+            Error::IpDetection { .. }
+            | Error::NoQuorum { .. }
+            | Error::Transport { .. }
+            | Error::ServerFailure { .. } => Some(Retry::Backoff),
+            Error::RateLimited { retry_after } => Some(after(*retry_after)),
+            Error::Api {
+                status: Some(status),
+                retry_after,
+                ..
+            } if *status >= 500 => Some(after(*retry_after)),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure may succeed on retry
+    pub fn is_retryable(&self) -> bool {
+        self.retry().is_some()
+    }
+
+    /// Whether zone or DNS record does not exist, so cached identifiers may be stale
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::ZoneNotFound { .. } | Error::RecordNotFound { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_retry() {
+        let after = Duration::from_secs(30);
+        let e = Error::RateLimited {
+            retry_after: Some(after),
+        };
+        assert_eq!(Some(Retry::After(after)), e.retry());
+        assert_eq!(
+            "rate limited by DNS provider, retry after 30s",
+            e.to_string()
+        );
+
+        let e = Error::Api {
+            status: Some(503),
+            message: "HTTP 503 Service Unavailable".into(),
+            retry_after: None,
+        };
+        assert_eq!(Some(Retry::Backoff), e.retry());
+        let e = Error::Api {
+            status: Some(400),
+            message: "HTTP 400 Bad Request".into(),
+            retry_after: None,
+        };
+        assert!(!e.is_retryable());
+        let e = Error::IpDetection {
+            record_type: RecordType::A,
+            source: "no answer from public IP resolvers".into(),
+        };
+        assert!(e.is_retryable());
+        let e = Error::ZoneNotFound {
+            zone: "zone".into(),
+        };
+        assert!(!e.is_retryable());
+        assert!(e.is_not_found());
+
+        assert_eq!(Retry::After(after), Retry::Backoff.max(Retry::After(after)));
+        assert_eq!(
+            Retry::After(after),
+            Retry::After(Duration::from_secs(1)).max(Retry::After(after))
+        );
+    }
+}
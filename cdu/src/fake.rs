@@ -0,0 +1,159 @@
+//! DNS provider holding DNS records in memory for tests, enabled by feature `test-util`
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::{CreateOptions, DnsProvider, Error, Record, RecordOptions, RecordType, Result};
+use futures::future::BoxFuture;
+
+/// Identifier of the zone held by [`FakeProvider`]
+pub const ZONE_ID: &str = "1";
+
+/// DNS provider holding DNS records of one zone in memory, identified by [`ZONE_ID`].
+/// Creations and updates fail with the given errors first, in order
+#[derive(Debug, Default)]
+pub struct FakeProvider {
+    records: Mutex<Vec<Record>>,
+    failures: Mutex<Vec<Error>>,
+    writes: AtomicUsize,
+}
+
+fn record_type_of(record: &Record) -> Option<RecordType> {
+    record.content.parse().ok().map(|ip| RecordType::of(&ip))
+}
+
+impl FakeProvider {
+    /// Creates a [`FakeProvider`] holding DNS records
+    pub fn new(records: Vec<Record>) -> Self {
+        Self {
+            records: Mutex::new(records),
+            ..Self::default()
+        }
+    }
+
+    /// Fail the first creations and updates with errors, in order
+    pub fn with_failures(self, failures: Vec<Error>) -> Self {
+        *self.failures.lock().unwrap() = failures;
+        self
+    }
+
+    /// DNS records held now
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().unwrap().clone()
+    }
+
+    /// Number of creations and updates, including failed ones
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::SeqCst)
+    }
+
+    /// Fail if zone identifier is not the one of the zone held
+    fn check_zone(zone_id: &str) -> Result<()> {
+        if zone_id != ZONE_ID {
+            return Err(Error::ZoneNotFound {
+                zone: zone_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Count a creation or update, then take the next error to fail with if any
+    fn write(&self) -> Result<()> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let mut failures = self.failures.lock().unwrap();
+        if failures.is_empty() {
+            return Ok(());
+        }
+        Err(failures.remove(0))
+    }
+}
+
+impl DnsProvider for FakeProvider {
+    fn zone_id<'a>(&'a self, _zone: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async { Ok(ZONE_ID.to_string()) })
+    }
+
+    fn get_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Option<Record>>> {
+        Box::pin(async move {
+            Self::check_zone(zone_id)?;
+            Ok(self
+                .records()
+                .into_iter()
+                .find(|r| r.name == name && record_type_of(r) == Some(record_type)))
+        })
+    }
+
+    fn list_records<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<Record>>> {
+        Box::pin(async move {
+            Self::check_zone(zone_id)?;
+            Ok(self
+                .records()
+                .into_iter()
+                .filter(|r| record_type_of(r) == Some(record_type))
+                .collect())
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: CreateOptions,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move {
+            Self::check_zone(zone_id)?;
+            self.write()?;
+            let mut records = self.records.lock().unwrap();
+            let id = (records.len() + 1).to_string();
+            records.push(Record {
+                id: id.clone(),
+                name: name.to_string(),
+                content: ip.to_string(),
+                ttl: options.ttl,
+                proxied: Some(options.proxied),
+            });
+            Ok(id)
+        })
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: RecordOptions,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            Self::check_zone(zone_id)?;
+            self.write()?;
+            let mut records = self.records.lock().unwrap();
+            let record = records
+                .iter_mut()
+                .find(|r| r.id == record_id)
+                .ok_or_else(|| Error::RecordNotFound {
+                    name: name.to_string(),
+                    record_type: RecordType::of(&ip),
+                })?;
+            record.content = ip.to_string();
+            if let Some(ttl) = options.ttl {
+                record.ttl = ttl;
+            }
+            if options.proxied.is_some() {
+                record.proxied = options.proxied;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,316 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use cdu::{RecordOutcome, RecordType, RunReport};
+use chrono::{DateTime, NaiveDate, TimeZone as _, Utc};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+/// DNS record touched by a change of IP address
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryRecord {
+    /// DNS record name
+    pub name: String,
+    /// Outcome of DNS record
+    pub outcome: RecordOutcome,
+    /// Error with its causes if DNS record fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Change of IP address of DNS records of a record type in a zone
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// Time of the run detecting the change
+    pub timestamp: DateTime<Utc>,
+    /// Zone name
+    pub zone: String,
+    /// DNS record type
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    /// IP address held before, if known
+    pub previous: Option<IpAddr>,
+    /// IP address published
+    pub ip: IpAddr,
+    /// DNS records touched
+    pub records: Vec<HistoryRecord>,
+}
+
+impl HistoryEntry {
+    /// Changes of IP address in report, one entry per record type and pair of IP addresses.
+    /// DNS records which keep their IP address are left out
+    pub fn from_report(report: &RunReport, timestamp: DateTime<Utc>) -> Vec<HistoryEntry> {
+        let mut entries: Vec<HistoryEntry> = vec![];
+        for r in &report.records {
+            if r.outcome == RecordOutcome::Unchanged || r.previous == Some(r.ip) {
+                continue;
+            }
+            let record = HistoryRecord {
+                name: r.name.clone(),
+                outcome: r.outcome,
+                error: r.error.clone(),
+            };
+            let entry = entries.iter_mut().find(|e| {
+                e.record_type == r.record_type && e.previous == r.previous && e.ip == r.ip
+            });
+            match entry {
+                Some(entry) => entry.records.push(record),
+                None => entries.push(HistoryEntry {
+                    timestamp,
+                    zone: report.zone.clone(),
+                    record_type: r.record_type,
+                    previous: r.previous,
+                    ip: r.ip,
+                    records: vec![record],
+                }),
+            }
+        }
+        entries
+    }
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let timestamp = self.timestamp.to_rfc3339();
+        let (zone, record_type, ip) = (&self.zone, self.record_type, self.ip);
+        match self.previous {
+            Some(previous) => write!(f, "{timestamp} {zone} ({record_type}): {previous} -> {ip}")?,
+            None => write!(f, "{timestamp} {zone} ({record_type}): (unknown) -> {ip}")?,
+        }
+        for (i, record) in self.records.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { ", " };
+            write!(f, "{separator}{} {}", record.name, record.outcome)?;
+            if let Some(error) = &record.error {
+                write!(f, " ({error})")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse time in RFC 3339 e.g. `2024-01-02T03:04:05Z`, or date e.g. `2024-01-02` at midnight UTC
+fn parse_time(s: &str) -> anyhow::Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| format!("invalid time, expect RFC 3339 or YYYY-MM-DD: {s}"))?;
+    Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default()))
+}
+
+/// Conditions on history entries, all of which must be met
+#[derive(Args, Clone, Debug, Default)]
+pub struct HistoryFilter {
+    /// Only changes in the zone
+    #[arg(long)]
+    pub zone: Option<String>,
+    /// Only changes touching the DNS record
+    #[arg(long)]
+    pub record: Option<String>,
+    /// Only changes from or to the IP address
+    #[arg(long)]
+    pub ip: Option<IpAddr>,
+    /// Only changes at or after the time, in RFC 3339 or YYYY-MM-DD
+    #[arg(long, value_parser = parse_time)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only changes before the time, in RFC 3339 or YYYY-MM-DD
+    #[arg(long, value_parser = parse_time)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl HistoryFilter {
+    /// Whether history entry meets all conditions
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.zone.as_ref().map_or(true, |zone| &entry.zone == zone)
+            && self
+                .record
+                .as_ref()
+                .map_or(true, |name| entry.records.iter().any(|r| &r.name == name))
+            && self
+                .ip
+                .map_or(true, |ip| entry.ip == ip || entry.previous == Some(ip))
+            && self.since.map_or(true, |since| entry.timestamp >= since)
+            && self.until.map_or(true, |until| entry.timestamp < until)
+    }
+}
+
+/// History file of IP address changes in JSON Lines, one entry per line
+#[derive(Clone, Debug)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// Creates a [`History`] on the file, which is created on first change
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Append entries to history file
+    pub fn append(&self, entries: &[HistoryEntry]) -> anyhow::Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut lines = vec![];
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        let path = &self.path;
+        // one write per run so that lines of concurrent zones do not interleave
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&lines))
+            .with_context(|| format!("cannot write history file: {}", path.display()))
+    }
+
+    /// All entries in order of changes, empty if history file does not exist
+    pub fn load(&self) -> anyhow::Result<Vec<HistoryEntry>> {
+        let path = &self.path;
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("cannot read history file: {}", path.display()))
+            }
+        };
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!("invalid history file: {} at line {}", path.display(), i + 1)
+                })
+            })
+            .collect()
+    }
+
+    /// Entry by its number as listed, starting from 1
+    pub fn entry(&self, number: usize) -> anyhow::Result<HistoryEntry> {
+        let mut entries = self.load()?;
+        if number == 0 || number > entries.len() {
+            anyhow::bail!(
+                "no history entry {number}, history file has {} entries",
+                entries.len()
+            );
+        }
+        Ok(entries.swap_remove(number - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cdu::RecordReport;
+
+    fn record(name: &str, outcome: RecordOutcome, previous: &str, ip: &str) -> RecordReport {
+        let ip: IpAddr = ip.parse().unwrap();
+        RecordReport {
+            name: name.into(),
+            record_type: RecordType::of(&ip),
+            outcome,
+            previous: previous.parse().ok(),
+            ip,
+            error: (outcome == RecordOutcome::Failed).then(|| "error".to_string()),
+            retry: None,
+        }
+    }
+
+    fn report() -> RunReport {
+        RunReport {
+            zone: "x.com".into(),
+            records: vec![
+                record("a.x.com", RecordOutcome::Updated, "1.1.1.1", "2.2.2.2"),
+                record("b.x.com", RecordOutcome::Failed, "1.1.1.1", "2.2.2.2"),
+                record("c.x.com", RecordOutcome::Unchanged, "2.2.2.2", "2.2.2.2"),
+                // settings change only
+                record("d.x.com", RecordOutcome::Updated, "2.2.2.2", "2.2.2.2"),
+                record("e.x.com", RecordOutcome::Created, "", "2.2.2.2"),
+                record("a.x.com", RecordOutcome::Updated, "::1", "::2"),
+            ],
+            lists: vec![],
+        }
+    }
+
+    fn time(s: &str) -> DateTime<Utc> {
+        parse_time(s).unwrap()
+    }
+
+    #[test]
+    fn t_from_report() {
+        let entries = HistoryEntry::from_report(&report(), time("2024-01-02T03:04:05Z"));
+        let lines: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "2024-01-02T03:04:05+00:00 x.com (A): 1.1.1.1 -> 2.2.2.2: a.x.com updated, b.x.com failed (error)",
+                "2024-01-02T03:04:05+00:00 x.com (A): (unknown) -> 2.2.2.2: e.x.com created",
+                "2024-01-02T03:04:05+00:00 x.com (AAAA): ::1 -> ::2: a.x.com updated",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn t_parse_time() {
+        assert_eq!(time("2024-01-02T00:00:00Z"), time("2024-01-02"));
+        assert_eq!(
+            time("2024-01-02T03:00:00Z"),
+            time("2024-01-02T05:00:00+02:00")
+        );
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn t_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = History::new(dir.path().join("history.jsonl"));
+        assert!(history.load().unwrap().is_empty());
+        assert!(history.entry(1).is_err());
+
+        let first = HistoryEntry::from_report(&report(), time("2024-01-02"));
+        history.append(&first).unwrap();
+        history.append(&[]).unwrap();
+        let mut report = report();
+        report.zone = "y.com".into();
+        let second = HistoryEntry::from_report(&report, time("2024-01-03"));
+        history.append(&second).unwrap();
+
+        let entries = history.load().unwrap();
+        assert_eq!(6, entries.len());
+        assert_eq!(first[..], entries[..3]);
+        assert_eq!(second[2], history.entry(6).unwrap());
+        assert!(history.entry(0).is_err());
+        assert!(history.entry(7).is_err());
+
+        let filter = HistoryFilter {
+            zone: Some("y.com".into()),
+            record: Some("e.x.com".into()),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(1, entries.iter().filter(|e| filter.matches(e)).count());
+        let filter = HistoryFilter {
+            ip: Some("1.1.1.1".parse().unwrap()),
+            until: Some(time("2024-01-03")),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(1, entries.iter().filter(|e| filter.matches(e)).count());
+        let filter = HistoryFilter {
+            since: Some(time("2024-01-03")),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(3, entries.iter().filter(|e| filter.matches(e)).count());
+
+        fs::write(dir.path().join("history.jsonl"), "{}\n").unwrap();
+        let e = history.load().unwrap_err();
+        assert!(e.to_string().ends_with("history.jsonl at line 1"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{bail, Context as _};
+use cdu::{RecordChange, RecordType};
+use log::{debug, info, warn};
+
+/// Change of IP address of DNS records of a record type in a zone, passed to hooks
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HookEvent {
+    /// Zone name
+    pub zone: String,
+    /// DNS record type
+    pub record_type: RecordType,
+    /// IP address held before, if known
+    pub previous: Option<IpAddr>,
+    /// IP address held now
+    pub current: IpAddr,
+    /// DNS records changed
+    pub records: Vec<String>,
+}
+
+impl HookEvent {
+    /// Events of changes, one per record type and pair of IP addresses
+    pub fn of(zone: &str, changes: &[RecordChange]) -> Vec<HookEvent> {
+        let mut events: Vec<HookEvent> = vec![];
+        for change in changes {
+            let event = events.iter_mut().find(|e| {
+                e.record_type == change.record_type
+                    && e.previous == change.previous
+                    && e.current == change.current
+            });
+            match event {
+                Some(event) => event.records.push(change.name.clone()),
+                None => events.push(HookEvent {
+                    zone: zone.to_string(),
+                    record_type: change.record_type,
+                    previous: change.previous,
+                    current: change.current,
+                    records: vec![change.name.clone()],
+                }),
+            }
+        }
+        events
+    }
+
+    /// Environment variables of hook commands, old IP address is empty if unknown
+    fn env(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("CDU_ZONE", self.zone.clone()),
+            ("CDU_RECORD_TYPE", self.record_type.to_string()),
+            (
+                "CDU_OLD_IP",
+                self.previous.map(|ip| ip.to_string()).unwrap_or_default(),
+            ),
+            ("CDU_NEW_IP", self.current.to_string()),
+            ("CDU_RECORDS", self.records.join(",")),
+        ]
+    }
+}
+
+/// Shell commands run after DNS records change IP address
+#[derive(Clone, Debug)]
+pub struct Hooks {
+    /// Commands run in order
+    pub commands: Vec<String>,
+    /// Time limit of each command
+    pub timeout: Duration,
+}
+
+impl Hooks {
+    /// Run all commands on each event, a failing command does not stop the others.
+    /// Returns failures of commands
+    pub async fn run(&self, events: &[HookEvent]) -> Vec<anyhow::Error> {
+        let mut failures = vec![];
+        for event in events {
+            for command in &self.commands {
+                if let Err(e) = self.run_command(command, event).await {
+                    failures.push(e);
+                }
+            }
+        }
+        failures
+    }
+
+    async fn run_command(&self, command: &str, event: &HookEvent) -> anyhow::Result<()> {
+        debug!("run hook {command:?} on {event:?}");
+        let child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(event.env())
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, child)
+            .await
+            .with_context(|| format!("hook timed out after {:?}: {command}", self.timeout))?
+            .with_context(|| format!("cannot run hook: {command}"))?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            info!("hook {command:?}: {line}");
+        }
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            warn!("hook {command:?}: {line}");
+        }
+        if !output.status.success() {
+            bail!("hook exited with {}: {command}", output.status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(name: &str, previous: Option<&str>, current: &str) -> RecordChange {
+        let current: IpAddr = current.parse().unwrap();
+        RecordChange {
+            name: name.into(),
+            record_type: RecordType::of(&current),
+            previous: previous.map(|ip| ip.parse().unwrap()),
+            current,
+        }
+    }
+
+    #[test]
+    fn t_hook_event() {
+        let changes = vec![
+            change("a.x.com", Some("1.1.1.1"), "1.1.1.2"),
+            change("b.x.com", Some("1.1.1.1"), "1.1.1.2"),
+            change("c.x.com", None, "1.1.1.2"),
+            change("a.x.com", Some("::1"), "::2"),
+        ];
+        let events = HookEvent::of("x.com", &changes);
+        assert_eq!(3, events.len());
+        assert_eq!(
+            vec![
+                ("CDU_ZONE", "x.com".to_string()),
+                ("CDU_RECORD_TYPE", "A".to_string()),
+                ("CDU_OLD_IP", "1.1.1.1".to_string()),
+                ("CDU_NEW_IP", "1.1.1.2".to_string()),
+                ("CDU_RECORDS", "a.x.com,b.x.com".to_string()),
+            ],
+            events[0].env()
+        );
+        assert_eq!("", events[1].env()[2].1);
+        assert_eq!(RecordType::AAAA, events[2].record_type);
+    }
+
+    #[tokio::test]
+    async fn t_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook.out");
+        let events = HookEvent::of("x.com", &[change("a.x.com", None, "1.1.1.2")]);
+        let hooks = Hooks {
+            commands: vec![
+                format!(
+                    r#"echo "$CDU_OLD_IP>$CDU_NEW_IP $CDU_RECORDS" > {}"#,
+                    path.display()
+                ),
+                "echo failing >&2; exit 3".into(),
+                "sleep 5".into(),
+            ],
+            timeout: Duration::from_millis(200),
+        };
+        let failures = hooks.run(&events).await;
+        assert_eq!(
+            ">1.1.1.2 a.x.com\n",
+            std::fs::read_to_string(&path).unwrap()
+        );
+        let failures: Vec<String> = failures.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "hook exited with exit status: 3: echo failing >&2; exit 3",
+                "hook timed out after 200ms: sleep 5",
+            ],
+            failures
+        );
+        assert!(hooks.run(&[]).await.is_empty());
+    }
+}
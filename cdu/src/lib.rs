@@ -13,310 +13,1838 @@
 //! Cloudflare DNS record update.
 
 use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::net::Ipv4Addr;
-use std::sync::Arc;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use anyhow::bail;
-use cloudflare::endpoints::dns::{DnsContent, DnsRecord};
-use cloudflare::endpoints::zone::Zone;
-use cloudflare::framework::response::ApiSuccess;
-use futures::stream::FuturesUnordered;
-use log::{debug, Level};
+use futures::stream::FuturesOrdered;
+use log::{debug, info, warn, Level};
 use logging_timer::{finish, stimer};
 use moka::sync::Cache;
-use ureq::{Agent, AgentBuilder};
+use serde::{Deserialize, Serialize};
 
-const HTTP_TIMEOUT: u64 = 30;
+pub use error::{BoxError, Error, Result, Retry};
+pub use ipnet::IpNet;
+pub use plan::{Plan, PlannedAction, PlannedChange};
+pub use provider::{
+    ApiClient, CloudflareIpList, CloudflareProvider, Credentials, DnsProvider, IpList, ListItem,
+    Record, RecordInfo, Rfc2136Provider, TokenStatus, ZoneInfo, DEFAULT_API_TIMEOUT,
+    DEFAULT_API_URL, DEFAULT_LIST_ITEM_COMMENT, DEFAULT_RFC2136_TTL,
+};
+pub use report::{RecordOutcome, RecordReport, RunReport};
+pub use selector::{parse_record_selector, RecordSelector};
+pub use source::{
+    parse_ip_source, CommandIpSource, HttpIpSource, InterfaceIpSource, IpSource, PublicIpSource,
+    QuorumIpSource, StaticIpSource,
+};
 
-#[cfg(not(test))]
-fn server_url() -> String {
-    "https://api.cloudflare.com".to_string()
+use crate::state::{RecordState, State};
+
+mod address;
+mod error;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fake;
+mod plan;
+mod provider;
+mod report;
+mod selector;
+mod source;
+mod state;
+
+/// IP address sources do not agree on IP address
+#[derive(Clone, Debug)]
+pub struct NoQuorum {
+    /// Number of sources required to agree
+    pub quorum: usize,
+    /// IP addresses answered and number of sources answering each of them
+    pub votes: Vec<(IpAddr, usize)>,
+    /// Number of sources failing to answer
+    pub failures: usize,
 }
 
-#[cfg(test)]
-fn server_url() -> String {
-    mockito::server_url()
+impl Display for NoQuorum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let quorum = self.quorum;
+        let failures = self.failures;
+        let votes = self
+            .votes
+            .iter()
+            .map(|(ip, n)| format!("{ip}={n}"))
+            .collect::<Vec<String>>()
+            .join(",");
+        write!(
+            f,
+            "IP address sources do not reach quorum of {quorum}: votes [{votes}], {failures} failure(s)"
+        )
+    }
 }
 
-/// Cannot fetch public IPv4 address
+impl std::error::Error for NoQuorum {}
+
+/// IP address must not be published in public DNS e.g. private, CGNAT or loopback address
 #[derive(Clone, Copy, Debug)]
-pub struct NoIPV4;
+pub struct NonPublicIP {
+    /// IP address refused
+    pub ip: IpAddr,
+    /// Name of the range the IP address belongs to
+    pub range: &'static str,
+}
+
+impl Display for NonPublicIP {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (ip, range) = (self.ip, self.range);
+        write!(f, "refuse to publish {ip} in {range} range")
+    }
+}
+
+impl std::error::Error for NonPublicIP {}
+
+/// Type of DNS record to update, which also decides the address family
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize)]
+pub enum RecordType {
+    /// A record, updated with public IPv4 address
+    A,
+    /// AAAA record, updated with public IPv6 address
+    AAAA,
+}
 
-impl Display for NoIPV4 {
+impl RecordType {
+    /// Record type of DNS records holding the IP address
+    pub fn of(ip: &IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::AAAA,
+        }
+    }
+}
+
+impl Display for RecordType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "cannot fetch public IPv4 address")
+        match self {
+            RecordType::A => write!(f, "A"),
+            RecordType::AAAA => write!(f, "AAAA"),
+        }
     }
 }
 
-impl std::error::Error for NoIPV4 {}
+impl FromStr for RecordType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(RecordType::A),
+            "AAAA" => Ok(RecordType::AAAA),
+            _ => Err(Error::UnsupportedRecordType {
+                record_type: s.to_string(),
+            }),
+        }
+    }
+}
 
-#[derive(Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Hash)]
 enum CacheKey {
-    LastIP,
+    LastIPv4,
+    LastIPv6,
+    ZoneID,
+    RecordID(String, RecordType),
 }
 
 #[derive(Clone)]
 enum Cached {
-    IP(Ipv4Addr),
+    IPv4(Ipv4Addr),
+    IPv6(Ipv6Addr),
+    Identifier(String),
+}
+
+impl Cached {
+    fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Cached::IPv4(i) => Some(IpAddr::V4(*i)),
+            Cached::IPv6(i) => Some(IpAddr::V6(*i)),
+            Cached::Identifier(_) => None,
+        }
+    }
+
+    fn identifier(self) -> Option<String> {
+        match self {
+            Cached::Identifier(i) => Some(i),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Cached {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Cached::IP(i) => write!(f, "{i}"),
-        }
-    }
-}
-
-async fn get_record_identifier<'a, T>(
-    agent: Arc<Agent>,
-    token: T,
-    zone_id: T,
-    record_name: T,
-) -> anyhow::Result<(String, String)>
-where
-    T: Into<Cow<'a, str>>,
-{
-    let token = token.into();
-    let authorization = format!("bearer {}", token);
-
-    let zone_id = zone_id.into();
-    let record_name = record_name.into();
-
-    let url = format!("{}/client/v4/zones/{zone_id}/dns_records", server_url());
-    let req = agent
-        .get(&url)
-        .query("name", &record_name)
-        .set("content-type", "application/json")
-        .set("authorization", &authorization);
-    let tmr = stimer!(Level::Debug; "FETCH_DNS_RECORD", "zone_id={zone_id}");
-    let res: ApiSuccess<Vec<DnsRecord>> = req.call()?.into_json()?;
-    let identifier = match res.result.first() {
-        Some(record) => record.id.clone(),
-        None => bail!("DNS record not found: {record_name}"),
-    };
-    finish!(tmr, "id={identifier}");
-    Ok((identifier, record_name.into()))
-}
-
-async fn update_dns_record<'a, T>(
-    agent: Arc<Agent>,
-    token: T,
-    zone_id: T,
-    dns_record_id: T,
-    dns_record_name: T,
-    current_ip: Ipv4Addr,
-) -> anyhow::Result<()>
-where
-    T: Into<Cow<'a, str>>,
-{
-    let token = token.into();
-    let authorization = format!("bearer {token}");
-
-    let zone_id = zone_id.into();
-    let dns_record_name = dns_record_name.into();
-    let dns_record_id = dns_record_id.into();
-
-    let url = format!(
-        "{}/client/v4/zones/{zone_id}/dns_records/{dns_record_id}",
-        server_url()
-    );
-    let req = agent.put(&url).set("authorization", &authorization);
-    let tmr = stimer!(Level::Debug; "UPDATE_DNS_RECORD", "zone_id={zone_id},dns_record_id={dns_record_id}");
-    let res: ApiSuccess<DnsRecord> = req
-        .send_json(ureq::json!({
-            "type": "A",
-            "name":dns_record_name,
-            "content": current_ip,
-            "ttl": 1 // 1 for automatic
-        }))?
-        .into_json()?;
-    let content = match res.result.content {
-        DnsContent::A { content } => content.to_string(),
-        _ => "(not an A record)".into(),
+            Cached::IPv4(i) => write!(f, "{i}"),
+            Cached::IPv6(i) => write!(f, "{i}"),
+            Cached::Identifier(i) => write!(f, "{i}"),
+        }
+    }
+}
+
+/// Output of spawned task, resuming its panic
+fn joined<T>(res: std::result::Result<T, tokio::task::JoinError>) -> T {
+    match res {
+        Ok(output) => output,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Whether DNS record holds the IP address and settings already
+fn is_up_to_date(record: &Record, current_ip: IpAddr, options: RecordOptions) -> bool {
+    let content_matches = record.content.parse::<IpAddr>().ok() == Some(current_ip);
+    // proxied setting is ignored by providers which do not support proxying
+    content_matches
+        && options.ttl.map_or(true, |ttl| ttl == record.ttl)
+        && match (options.proxied, record.proxied) {
+            (Some(p), Some(proxied)) => p == proxied,
+            _ => true,
+        }
+}
+
+/// Settings of DNS record to set explicitly on update, unset ones are kept as they are
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RecordOptions {
+    /// Time to live, 1 for automatic
+    pub ttl: Option<u32>,
+    /// Whether the record is proxied by Cloudflare
+    pub proxied: Option<bool>,
+}
+
+impl RecordOptions {
+    /// Overrides settings with ones set in `other`
+    pub fn merge(self, other: RecordOptions) -> Self {
+        Self {
+            ttl: other.ttl.or(self.ttl),
+            proxied: other.proxied.or(self.proxied),
+        }
+    }
+}
+
+/// Settings of DNS record created when it does not exist
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CreateOptions {
+    /// Time to live, 1 for automatic
+    pub ttl: u32,
+    /// Whether the record is proxied by Cloudflare
+    pub proxied: bool,
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        Self {
+            ttl: 1,
+            proxied: false,
+        }
+    }
+}
+
+impl CreateOptions {
+    fn merge(self, other: RecordOptions) -> Self {
+        Self {
+            ttl: other.ttl.unwrap_or(self.ttl),
+            proxied: other.proxied.unwrap_or(self.proxied),
+        }
+    }
+}
+
+fn plan_change(
+    record: Option<Record>,
+    name: String,
+    current_ip: IpAddr,
+    options: RecordOptions,
+    create_missing: Option<CreateOptions>,
+) -> PlannedChange {
+    let record_type = RecordType::of(&current_ip);
+    let mut change = PlannedChange {
+        name,
+        record_type,
+        action: PlannedAction::Missing,
+        current_content: None,
+        new_content: current_ip,
+        ttl: None,
+        proxied: None,
     };
-    finish!(tmr, "content={content}");
-    Ok(())
+    match (record, create_missing) {
+        (Some(r), _) => {
+            change.action = if is_up_to_date(&r, current_ip, options) {
+                PlannedAction::Unchanged
+            } else {
+                PlannedAction::Update
+            };
+            change.ttl = options
+                .ttl
+                .filter(|ttl| *ttl != r.ttl)
+                .map(|ttl| (Some(r.ttl), ttl));
+            change.proxied = match (options.proxied, r.proxied) {
+                (Some(p), Some(proxied)) if p != proxied => Some((Some(proxied), p)),
+                _ => None,
+            };
+            change.current_content = Some(r.content);
+        }
+        (None, Some(create_options)) => {
+            let create_options = create_options.merge(options);
+            change.action = PlannedAction::Create;
+            change.ttl = Some((None, create_options.ttl));
+            change.proxied = Some((None, create_options.proxied));
+        }
+        (None, None) => {}
+    }
+    change
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Action {
+    Create,
+    Update,
+    Skip,
+}
+
+/// DNS record resolved in a run
+struct ResolvedRecord {
+    id: String,
+    name: String,
+    ip: IpAddr,
+    previous_ip: Option<IpAddr>,
+    action: Action,
+}
+
+/// Result of one DNS record in a run, converted to [`RecordReport`] at last
+struct RecordResult {
+    name: String,
+    /// Whether the result is of an IP list rather than a DNS record
+    list: bool,
+    record_type: RecordType,
+    ip: IpAddr,
+    previous_ip: Option<IpAddr>,
+    result: Result<ResolvedRecord>,
+}
+
+impl RecordResult {
+    fn is_same_record(&self, other: &RecordResult) -> bool {
+        self.name == other.name && self.record_type == other.record_type && self.list == other.list
+    }
+
+    fn report(self) -> RecordReport {
+        let (name, record_type) = (self.name, self.record_type);
+        match self.result {
+            Ok(record) => RecordReport {
+                name,
+                record_type,
+                outcome: match record.action {
+                    Action::Create => RecordOutcome::Created,
+                    Action::Update => RecordOutcome::Updated,
+                    Action::Skip => RecordOutcome::Unchanged,
+                },
+                previous: record.previous_ip,
+                ip: record.ip,
+                error: None,
+                retry: None,
+            },
+            Err(e) => RecordReport {
+                name,
+                record_type,
+                outcome: RecordOutcome::Failed,
+                previous: self.previous_ip,
+                ip: self.ip,
+                retry: e.retry(),
+                error: Some(format!("{:#}", anyhow::Error::from(e))),
+            },
+        }
+    }
+}
+
+/// Resolve one DNS record, then create or update it
+struct RecordTask {
+    provider: Arc<dyn DnsProvider>,
+    zone_id: String,
+    name: String,
+    record_type: RecordType,
+    ip: IpAddr,
+    last_ip: Option<IpAddr>,
+    options: RecordOptions,
+    create_missing: Option<CreateOptions>,
+}
+
+impl RecordTask {
+    async fn resolve(&self, cached_id: Option<String>) -> Result<ResolvedRecord> {
+        let (name, record_type, ip, options) =
+            (&self.name, self.record_type, self.ip, self.options);
+        let (id, previous_ip, action) = match cached_id {
+            // published in last run which failed on other DNS records
+            Some(id) if self.last_ip == Some(ip) => (Some(id), self.last_ip, Action::Skip),
+            // DNS record may hold the IP address already e.g. set by others, compare it
+            _ => {
+                let record = self
+                    .provider
+                    .get_record(&self.zone_id, name, record_type)
+                    .await?;
+                match record {
+                    Some(r) => {
+                        let previous_ip = r.content.parse().ok();
+                        let action = if is_up_to_date(&r, ip, options) {
+                            Action::Skip
+                        } else {
+                            Action::Update
+                        };
+                        (Some(r.id), previous_ip, action)
+                    }
+                    None => (None, None, Action::Create),
+                }
+            }
+        };
+        let id = match (id, self.create_missing) {
+            (Some(id), _) => id,
+            // created record holds current IP address already
+            (None, Some(create_options)) => {
+                self.provider
+                    .create_record(&self.zone_id, name, ip, create_options.merge(options))
+                    .await?
+            }
+            (None, None) => {
+                return Err(Error::RecordNotFound {
+                    name: name.clone(),
+                    record_type,
+                })
+            }
+        };
+        if action == Action::Update {
+            self.provider
+                .update_record(&self.zone_id, &id, name, ip, options)
+                .await?;
+        }
+        Ok(ResolvedRecord {
+            id,
+            name: name.clone(),
+            ip,
+            previous_ip,
+            action,
+        })
+    }
+
+    /// Resolve and publish, retry with DNS record looked up again if it is not found
+    /// by cached identifier, which may be stale
+    async fn run(self, cached_id: Option<String>) -> Result<ResolvedRecord> {
+        let is_cached = cached_id.is_some();
+        match self.resolve(cached_id).await {
+            Err(e @ Error::RecordNotFound { .. }) if is_cached => {
+                let (name, record_type) = (&self.name, self.record_type);
+                debug!("cached identifier of {name} ({record_type}) may be stale, retry: {e}");
+                self.resolve(None).await
+            }
+            res => res,
+        }
+    }
+}
+
+/// Parse IP list item as a range, a single IP address is a range of itself
+fn parse_item(item: &str) -> Option<IpNet> {
+    item.parse::<IpNet>()
+        .ok()
+        .or_else(|| item.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Items of an IP list to keep and replace, so that the list holds the IP address
+/// in place of the previous one
+struct ListSync {
+    /// Item holding the IP address, if any
+    current: Option<ListItem>,
+    /// Items holding a previous IP address of the same address family, either added by cdu
+    /// or holding the IP address last published
+    stale: Vec<ListItem>,
+}
+
+impl ListSync {
+    fn new(list: &dyn IpList, items: Vec<ListItem>, ip: IpAddr, last_ip: Option<IpAddr>) -> Self {
+        let target = parse_item(&list.item_of(ip));
+        let last = last_ip.and_then(|ip| parse_item(&list.item_of(ip)));
+        let mut sync = ListSync {
+            current: None,
+            stale: vec![],
+        };
+        for item in items {
+            let net = match parse_item(&item.ip) {
+                Some(net) if RecordType::of(&net.addr()) == RecordType::of(&ip) => net,
+                _ => continue,
+            };
+            if Some(net) == target {
+                sync.current = Some(item);
+            } else if item.comment.as_deref() == Some(list.comment()) || Some(net) == last {
+                sync.stale.push(item);
+            }
+        }
+        sync
+    }
+
+    fn action(&self) -> Action {
+        match (&self.current, self.stale.is_empty()) {
+            (Some(_), true) => Action::Skip,
+            (None, true) => Action::Create,
+            (_, false) => Action::Update,
+        }
+    }
+
+    /// Content of items replaced, or item kept
+    fn content(&self) -> Option<String> {
+        match (&self.current, self.stale.is_empty()) {
+            (Some(item), true) => Some(item.ip.clone()),
+            (_, false) => Some(
+                self.stale
+                    .iter()
+                    .map(|i| i.ip.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(","),
+            ),
+            (None, true) => None,
+        }
+    }
+}
+
+/// Name of IP list in reports
+fn list_name(list: &dyn IpList) -> String {
+    format!("list:{}", list.name())
+}
+
+/// Add item holding IP address to IP list, then remove items of previous IP addresses
+async fn sync_list(
+    list: Arc<dyn IpList>,
+    ip: IpAddr,
+    last_ip: Option<IpAddr>,
+) -> Result<ResolvedRecord> {
+    let items = list.items().await?;
+    let sync = ListSync::new(list.as_ref(), items, ip, last_ip);
+    if sync.current.is_none() {
+        list.add_item(ip).await?;
+    }
+    if !sync.stale.is_empty() {
+        let ids: Vec<String> = sync.stale.iter().map(|i| i.id.clone()).collect();
+        list.remove_items(&ids).await?;
+    }
+    let previous_ip = last_ip.or_else(|| {
+        sync.stale
+            .first()
+            .and_then(|i| parse_item(&i.ip))
+            .map(|n| n.addr())
+    });
+    Ok(ResolvedRecord {
+        id: list.name().to_string(),
+        name: list_name(list.as_ref()),
+        ip,
+        previous_ip,
+        action: sync.action(),
+    })
+}
+
+/// IP address of a DNS record changed in a run
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordChange {
+    /// DNS record name
+    pub name: String,
+    /// DNS record type
+    pub record_type: RecordType,
+    /// IP address held before, if known
+    pub previous: Option<IpAddr>,
+    /// IP address held now
+    pub current: IpAddr,
+}
+
+impl Display for RecordChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, record_type, current) = (&self.name, self.record_type, self.current);
+        match self.previous {
+            Some(previous) => write!(f, "{name} ({record_type}): {previous} -> {current}"),
+            None => write!(f, "{name} ({record_type}): (unknown) -> {current}"),
+        }
+    }
+}
+
+/// Default time span to cache zone and record identifiers
+pub const DEFAULT_IDENTIFIER_TTL: Duration = Duration::from_secs(86400);
+
+fn build_identifier_cache(ttl: Duration) -> Cache<CacheKey, Cached> {
+    Cache::builder()
+        .max_capacity(1024)
+        .time_to_live(ttl)
+        .build()
 }
 
 /// Cloudflare DNS Update
 pub struct Cdu<'a> {
-    token: Cow<'a, str>,
+    provider: Arc<dyn DnsProvider>,
     zone: Cow<'a, str>,
     record_names: Vec<String>,
+    record_types: Vec<RecordType>,
+    create_missing: Option<CreateOptions>,
+    record_options: RecordOptions,
+    options_by_record: HashMap<String, RecordOptions>,
+    ip_source: Arc<dyn IpSource>,
+    allowed_networks: Vec<IpNet>,
+    ip_lists: Vec<Arc<dyn IpList>>,
     cache: Cache<CacheKey, Cached>,
+    identifiers: Cache<CacheKey, Cached>,
+    state_file: Option<PathBuf>,
+    state: Mutex<State>,
 }
 
 impl<'a> std::fmt::Debug for Cdu<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Cdu")
-            .field("token", &self.token)
+            .field("provider", &self.provider)
             .field("zone", &self.zone)
             .field("record_names", &self.record_names)
+            .field("record_types", &self.record_types)
+            .field("create_missing", &self.create_missing)
+            .field("record_options", &self.record_options)
+            .field("options_by_record", &self.options_by_record)
+            .field("ip_source", &self.ip_source)
+            .field("allowed_networks", &self.allowed_networks)
+            .field("ip_lists", &self.ip_lists)
+            .field("state_file", &self.state_file)
             .finish()
     }
 }
 
+/// Split record name and record type e.g. `a.x.com:AAAA`
+fn parse_record_name(s: &str) -> (&str, Option<RecordType>) {
+    match s.rsplit_once(':') {
+        Some((name, t)) => match t.parse() {
+            Ok(t) => (name, Some(t)),
+            Err(_) => (s, None),
+        },
+        None => (s, None),
+    }
+}
+
 impl<'a> Cdu<'a> {
-    /// Creates a [`Cdu`]
+    /// Creates a [`Cdu`] updating DNS records with [`CloudflareProvider`] authenticated
+    /// with Cloudflare API token, see [`Cdu::from_provider`]
     pub fn new<T, U>(token: T, zone: T, record_names: &'a [U]) -> Self
+    where
+        T: Into<Cow<'a, str>>,
+        U: Display,
+    {
+        let token: Cow<'a, str> = token.into();
+        let provider = Arc::new(CloudflareProvider::new(token.into_owned()));
+        Self::from_provider(provider, zone, record_names)
+    }
+
+    /// Creates a [`Cdu`] updating DNS records with DNS provider
+    ///
+    /// Record name may be suffixed with record type e.g. `a.x.com:AAAA`
+    /// to override record types of the [`Cdu`] for that record.
+    /// Records may be selected by pattern e.g. `glob:*.x.com` or `regex:^(a|b)\.x\.com$`,
+    /// see [`RecordSelector`].
+    pub fn from_provider<T, U>(
+        provider: Arc<dyn DnsProvider>,
+        zone: T,
+        record_names: &'a [U],
+    ) -> Self
     where
         T: Into<Cow<'a, str>>,
         U: Display,
     {
         Self {
-            token: token.into(),
+            provider,
             zone: zone.into(),
             record_names: record_names
                 .iter()
                 .map(|s| s.to_string())
                 .collect::<Vec<String>>(),
-            cache: Cache::new(1), // cache IP address
+            record_types: vec![RecordType::A],
+            create_missing: None,
+            record_options: RecordOptions::default(),
+            options_by_record: HashMap::new(),
+            ip_source: Arc::new(PublicIpSource),
+            allowed_networks: vec![],
+            ip_lists: vec![],
+            cache: Cache::new(2), // cache IPv4 and IPv6 addresses
+            identifiers: build_identifier_cache(DEFAULT_IDENTIFIER_TTL),
+            state_file: None,
+            state: Mutex::new(State::default()),
         }
     }
 
-    fn build_agent(&self) -> Agent {
-        AgentBuilder::new()
-            .timeout(Duration::from_secs(HTTP_TIMEOUT))
-            .build()
+    /// Name of the zone
+    pub fn zone(&self) -> &str {
+        &self.zone
+    }
+
+    /// DNS provider holding DNS records
+    pub fn provider(&self) -> &Arc<dyn DnsProvider> {
+        &self.provider
+    }
+
+    /// Set DNS provider holding DNS records e.g. to wrap the one given on creation
+    pub fn with_provider(mut self, provider: Arc<dyn DnsProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Set record types to update for records without explicit record type, defaults to A
+    pub fn with_record_types(mut self, record_types: &[RecordType]) -> Self {
+        self.record_types = record_types.to_vec();
+        self
+    }
+
+    /// Create DNS records which do not exist with given settings, instead of failing
+    pub fn with_create_missing(mut self, create_missing: Option<CreateOptions>) -> Self {
+        self.create_missing = create_missing;
+        self
     }
 
-    async fn get_zone_identifier(&self, agent: Arc<Agent>) -> anyhow::Result<String> {
-        let zone = &self.zone;
-        let token = &self.token;
-        let req = agent
-            .get(&format!("{}/client/v4/zones", server_url()))
-            .set("accept", "application/json")
-            .set("authorization", &format!("bearer {token}"))
-            .query("name", &self.zone);
-        let tmr = stimer!(Level::Debug; "FETCH_ZONE", "zone={zone}");
-        let res: ApiSuccess<Vec<Zone>> = req.call()?.into_json()?;
-        let id = match res.result.first() {
-            Some(zone) => zone.id.to_string(),
-            None => bail!("zone not found: {zone}"),
+    /// Set settings of all DNS records to set explicitly on update
+    pub fn with_record_options(mut self, record_options: RecordOptions) -> Self {
+        self.record_options = record_options;
+        self
+    }
+
+    /// Set settings of one DNS record to set explicitly on update,
+    /// which take precedence over ones set with [`Cdu::with_record_options`]
+    pub fn with_options_for<T: Display>(mut self, record_name: T, options: RecordOptions) -> Self {
+        let record_name = record_name.to_string();
+        let options = match self.options_by_record.get(&record_name) {
+            Some(o) => o.merge(options),
+            None => options,
         };
-        finish!(tmr, "zone_id={id}");
-        Ok(id)
+        self.options_by_record.insert(record_name, options);
+        self
     }
 
-    /// Perform DNS record update on Cloudflare
-    pub async fn run(&self) -> anyhow::Result<()> {
-        use futures::StreamExt as _;
+    fn options_of(&self, record_name: &str) -> RecordOptions {
+        match self.options_by_record.get(record_name) {
+            Some(o) => self.record_options.merge(*o),
+            None => self.record_options,
+        }
+    }
+
+    /// Set source of IP address, defaults to [`PublicIpSource`]
+    pub fn with_ip_source(mut self, ip_source: Arc<dyn IpSource>) -> Self {
+        self.ip_source = ip_source;
+        self
+    }
+
+    /// Allow publishing IP addresses in networks which are refused by default
+    /// e.g. private addresses for split-horizon DNS
+    pub fn with_allowed_networks(mut self, allowed_networks: &[IpNet]) -> Self {
+        self.allowed_networks = allowed_networks.to_vec();
+        self
+    }
+
+    /// Keep IP list holding the IP addresses published, in place of previous ones.
+    /// The IP list is updated for all record types of the [`Cdu`], whenever DNS records are
+    pub fn with_ip_list(mut self, ip_list: Arc<dyn IpList>) -> Self {
+        self.ip_lists.push(ip_list);
+        self
+    }
+
+    /// Whether IP address of the record type is published in a run
+    fn publishes(&self, records: &[(RecordSelector, RecordType)], record_type: RecordType) -> bool {
+        records.iter().any(|(_, t)| *t == record_type)
+            || (!self.ip_lists.is_empty() && self.record_types.contains(&record_type))
+    }
+
+    fn validate_ip(&self, ip: IpAddr) -> Result<(), NonPublicIP> {
+        if self.allowed_networks.iter().any(|n| n.contains(&ip)) {
+            return Ok(());
+        }
+        match address::non_public_range(&ip) {
+            Some(range) => Err(NonPublicIP { ip, range }),
+            None => Ok(()),
+        }
+    }
+
+    /// Set time span to cache zone and record identifiers, defaults to [`DEFAULT_IDENTIFIER_TTL`]
+    pub fn with_identifier_ttl(mut self, ttl: Duration) -> Self {
+        let identifiers = build_identifier_cache(ttl);
+        for (key, value) in self.identifiers.iter() {
+            identifiers.insert(key.as_ref().clone(), value);
+        }
+        self.identifiers = identifiers;
+        self
+    }
+
+    /// Persist last published IP addresses, zone and record identifiers to file,
+    /// and load them from the file if it exists
+    pub fn with_state_file<P: Into<PathBuf>>(mut self, state_file: P) -> Result<Self> {
+        let state_file = state_file.into();
+        let mut state = State::load(&state_file).map_err(|e| Error::State(e.into()))?;
+        if state.zone != self.zone {
+            // records of another zone must not be taken for records of this zone
+            if !state.zone.is_empty() {
+                warn!(
+                    "state file {} holds zone {}, start afresh for zone {}",
+                    state_file.display(),
+                    state.zone,
+                    self.zone
+                );
+            }
+            state = State {
+                zone: self.zone.to_string(),
+                ..State::default()
+            };
+        }
+        if let Some(zone_id) = &state.zone_id {
+            self.identifiers
+                .insert(CacheKey::ZoneID, Cached::Identifier(zone_id.clone()));
+        }
+        for record in &state.records {
+            self.identifiers.insert(
+                CacheKey::RecordID(record.name.clone(), record.record_type),
+                Cached::Identifier(record.id.clone()),
+            );
+        }
+        for (record_type, ip) in &state.last_ips {
+            debug!("last {record_type} address {ip} loaded from state file");
+            match ip {
+                IpAddr::V4(i) => self.cache.insert(CacheKey::LastIPv4, Cached::IPv4(*i)),
+                IpAddr::V6(i) => self.cache.insert(CacheKey::LastIPv6, Cached::IPv6(*i)),
+            }
+        }
+        self.state = Mutex::new(state);
+        self.state_file = Some(state_file);
+        Ok(self)
+    }
+
+    fn records(&self) -> Result<Vec<(RecordSelector, RecordType)>> {
+        let mut records = vec![];
+        for record_name in &self.record_names {
+            match parse_record_selector(record_name)? {
+                (selector, Some(t)) => records.push((selector, t)),
+                (selector, None) => {
+                    for t in &self.record_types {
+                        records.push((selector.clone(), *t));
+                    }
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Expand record selectors to names of DNS records, listing DNS records of the zone
+    /// once per record type if any pattern is given
+    async fn expand(
+        &self,
+        zone_id: &str,
+        records: Vec<(RecordSelector, RecordType)>,
+    ) -> Result<Vec<(String, RecordType)>> {
+        let mut listed: HashMap<RecordType, Vec<Record>> = HashMap::new();
+        let mut expanded: Vec<(String, RecordType)> = vec![];
+        for (selector, record_type) in records {
+            let names = match selector {
+                RecordSelector::Name(name) => vec![name],
+                pattern => {
+                    let all = match listed.entry(record_type) {
+                        Entry::Occupied(e) => e.into_mut(),
+                        Entry::Vacant(e) => {
+                            e.insert(self.provider.list_records(zone_id, record_type).await?)
+                        }
+                    };
+                    let names: Vec<String> = all
+                        .iter()
+                        .filter(|r| pattern.matches(&r.name))
+                        .map(|r| r.name.clone())
+                        .collect();
+                    if names.is_empty() {
+                        warn!("no {record_type} record matches {pattern}");
+                    }
+                    names
+                }
+            };
+            for name in names {
+                if !expanded
+                    .iter()
+                    .any(|(n, t)| *n == name && *t == record_type)
+                {
+                    expanded.push((name, record_type));
+                }
+            }
+        }
+        Ok(expanded)
+    }
 
-        let tmr = stimer!(Level::Debug; "FETCH_IP_ADDRESS");
-        let current_ip = public_ip::addr_v4().await.ok_or(NoIPV4)?;
+    async fn fetch_current_ip(&self, record_type: RecordType) -> Result<IpAddr> {
+        let tmr = stimer!(Level::Debug; "FETCH_IP_ADDRESS", "record_type={record_type}");
+        let fetched = self.ip_source.fetch(record_type).await.and_then(|ip| {
+            if RecordType::of(&ip) != record_type {
+                return Err(format!("{ip} is not an address for {record_type} records").into());
+            }
+            Ok(ip)
+        });
+        let current_ip = fetched.map_err(|source| match source.downcast::<NoQuorum>() {
+            Ok(no_quorum) => Error::NoQuorum {
+                record_type,
+                votes: no_quorum.votes,
+                required: no_quorum.quorum,
+                failures: no_quorum.failures,
+            },
+            Err(source) => Error::IpDetection {
+                record_type,
+                source,
+            },
+        })?;
         finish!(tmr, "current_ip={current_ip:?}");
+        self.validate_ip(current_ip)?;
+        Ok(current_ip)
+    }
+
+    async fn fetch_ip(&self, record_type: RecordType) -> Result<Option<IpAddr>> {
+        let current_ip = self.fetch_current_ip(record_type).await?;
+        let key = match current_ip {
+            IpAddr::V4(_) => CacheKey::LastIPv4,
+            IpAddr::V6(_) => CacheKey::LastIPv6,
+        };
 
-        if let Some(Cached::IP(last_ip)) = self.cache.get(&CacheKey::LastIP) {
-            if current_ip == last_ip {
-                debug!("IPv4 address remains unchanged, skip");
-                return Ok(());
+        if let Some(last_ip) = self.cache.get(&key) {
+            if last_ip.ip() == Some(current_ip) {
+                debug!("{record_type} address remains unchanged, skip");
+                return Ok(None);
             }
-            debug!("IPv4 address changed from {last_ip} to {current_ip}");
+            debug!("{record_type} address changed from {last_ip} to {current_ip}");
         } else {
-            debug!("no previous IPv4 address found, continue");
+            debug!("no previous {record_type} address found, continue");
+        }
+        Ok(Some(current_ip))
+    }
+
+    /// Plan DNS record changes without performing them,
+    /// regardless of cached IP addresses and identifiers
+    pub async fn plan(&self) -> Result<Plan> {
+        use futures::StreamExt as _;
+
+        let records = self.records()?;
+
+        let mut current_ips = vec![];
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            if self.publishes(&records, record_type) {
+                current_ips.push(self.fetch_current_ip(record_type).await?);
+            }
         }
 
-        let agent = Arc::new(self.build_agent());
-        let zone_id = self.get_zone_identifier(agent.clone()).await?;
+        let zone_id = self.provider.zone_id(&self.zone).await?;
+        let records = self.expand(&zone_id, records).await?;
 
-        let mut tasks = FuturesUnordered::new();
-        for record_name in &self.record_names {
-            let agent = agent.clone();
-            let token = self.token.to_string();
+        let mut tasks = FuturesOrdered::new();
+        for (record_name, record_type) in records {
+            let current_ip = match current_ips
+                .iter()
+                .find(|i| RecordType::of(i) == record_type)
+            {
+                Some(i) => *i,
+                None => continue,
+            };
+            let provider = self.provider.clone();
             let zone_id = zone_id.clone();
-            let record_name = record_name.clone();
-            tasks.push(tokio::spawn(async move {
-                get_record_identifier(agent, token, zone_id, record_name).await
-            }))
+            let options = self.options_of(&record_name);
+            let create_missing = self.create_missing;
+            tasks.push_back(tokio::spawn(async move {
+                let record = provider
+                    .get_record(&zone_id, &record_name, record_type)
+                    .await?;
+                Ok::<_, Error>(plan_change(
+                    record,
+                    record_name,
+                    current_ip,
+                    options,
+                    create_missing,
+                ))
+            }));
+        }
+
+        for list in &self.ip_lists {
+            for current_ip in &current_ips {
+                let (list, current_ip) = (list.clone(), *current_ip);
+                let record_type = RecordType::of(&current_ip);
+                let last_ip = self.last_ip(&list_name(list.as_ref()), record_type);
+                tasks.push_back(tokio::spawn(async move {
+                    let sync =
+                        ListSync::new(list.as_ref(), list.items().await?, current_ip, last_ip);
+                    Ok(PlannedChange {
+                        name: list_name(list.as_ref()),
+                        record_type,
+                        action: match sync.action() {
+                            Action::Create => PlannedAction::Create,
+                            Action::Update => PlannedAction::Update,
+                            Action::Skip => PlannedAction::Unchanged,
+                        },
+                        current_content: sync.content(),
+                        new_content: current_ip,
+                        ttl: None,
+                        proxied: None,
+                    })
+                }));
+            }
         }
 
-        let mut record_identifiers = vec![];
+        let mut changes = vec![];
         while let Some(task) = tasks.next().await {
-            let (id, name) = task??;
-            record_identifiers.push((id, name));
+            changes.push(joined(task)?);
         }
+        Ok(Plan {
+            zone: self.zone.to_string(),
+            changes,
+        })
+    }
 
-        let mut tasks = FuturesUnordered::new();
-        for (id, name) in record_identifiers {
-            let agent = agent.clone();
-            let token = self.token.to_string();
-            let zone_id = zone_id.clone();
-            tasks.push(tokio::spawn(async move {
-                update_dns_record(agent, token, zone_id, id, name, current_ip).await
+    /// Perform DNS record update, returns outcomes of DNS records.
+    /// DNS records succeed or fail independently, failures are reported in [`RunReport`]
+    pub async fn run(&self) -> Result<RunReport> {
+        let stale = |e: &Error| {
+            matches!(e, Error::ZoneNotFound { .. }) && self.identifiers.iter().next().is_some()
+        };
+        let results = match self.do_run().await {
+            Err(e) if stale(&e) => {
+                debug!("cached identifiers may be stale, invalidate and retry: {e}");
+                self.identifiers.invalidate_all();
+                self.do_run().await?
+            }
+            Ok(results)
+                if results
+                    .iter()
+                    .any(|r| matches!(&r.result, Err(e) if stale(e))) =>
+            {
+                debug!("cached zone identifier may be stale, invalidate and retry");
+                self.identifiers.invalidate_all();
+                let mut retried = self.do_run().await?;
+                // DNS records which succeed at first keep their outcomes
+                results
+                    .into_iter()
+                    .map(|r| {
+                        if r.result.is_err() {
+                            if let Some(i) = retried.iter().position(|t| t.is_same_record(&r)) {
+                                return retried.swap_remove(i);
+                            }
+                        }
+                        r
+                    })
+                    .collect()
+            }
+            res => res?,
+        };
+        let (lists, records): (Vec<RecordResult>, Vec<RecordResult>) =
+            results.into_iter().partition(|r| r.list);
+        Ok(RunReport {
+            zone: self.zone.to_string(),
+            records: records.into_iter().map(RecordResult::report).collect(),
+            lists: lists.into_iter().map(RecordResult::report).collect(),
+        })
+    }
+
+    /// IP address last published for the record, from state or cache
+    fn last_ip(&self, record_name: &str, record_type: RecordType) -> Option<IpAddr> {
+        let state = self.state.lock().expect("state lock poisoned");
+        if let Some(record) = state.record(record_name, record_type) {
+            return Some(record.ip);
+        }
+        let key = match record_type {
+            RecordType::A => CacheKey::LastIPv4,
+            RecordType::AAAA => CacheKey::LastIPv6,
+        };
+        self.cache.get(&key).and_then(|c| c.ip())
+    }
+
+    async fn do_run(&self) -> Result<Vec<RecordResult>> {
+        use futures::StreamExt as _;
+
+        let records = self.records()?;
+
+        let mut current_ips = vec![];
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            if !self.publishes(&records, record_type) {
+                continue;
+            }
+            if let Some(current_ip) = self.fetch_ip(record_type).await? {
+                current_ips.push(current_ip);
+            }
+        }
+        if current_ips.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let cached_zone_id = self
+            .identifiers
+            .get(&CacheKey::ZoneID)
+            .and_then(Cached::identifier);
+        let zone_id = match cached_zone_id {
+            Some(zone_id) => zone_id,
+            None => {
+                let zone_id = self.provider.zone_id(&self.zone).await?;
+                self.identifiers
+                    .insert(CacheKey::ZoneID, Cached::Identifier(zone_id.clone()));
+                zone_id
+            }
+        };
+        let records = self.expand(&zone_id, records).await?;
+
+        let mut tasks = FuturesOrdered::new();
+        for (record_name, record_type) in records {
+            let current_ip = match current_ips
+                .iter()
+                .find(|i| RecordType::of(i) == record_type)
+            {
+                Some(i) => *i,
+                None => continue,
+            };
+            let cached_id = self
+                .identifiers
+                .get(&CacheKey::RecordID(record_name.clone(), record_type))
+                .and_then(Cached::identifier);
+            let task = RecordTask {
+                provider: self.provider.clone(),
+                zone_id: zone_id.clone(),
+                name: record_name.clone(),
+                record_type,
+                ip: current_ip,
+                last_ip: self.last_ip(&record_name, record_type),
+                options: self.options_of(&record_name),
+                create_missing: self.create_missing,
+            };
+            tasks.push_back(tokio::spawn(async move {
+                let previous_ip = task.last_ip;
+                let result = task.run(cached_id).await;
+                RecordResult {
+                    name: record_name,
+                    list: false,
+                    record_type,
+                    ip: current_ip,
+                    previous_ip,
+                    result,
+                }
             }));
         }
 
+        for list in &self.ip_lists {
+            for current_ip in &current_ips {
+                let (list, current_ip) = (list.clone(), *current_ip);
+                let name = list_name(list.as_ref());
+                let record_type = RecordType::of(&current_ip);
+                let last_ip = self.last_ip(&name, record_type);
+                tasks.push_back(tokio::spawn(async move {
+                    let result = sync_list(list, current_ip, last_ip).await;
+                    RecordResult {
+                        name,
+                        list: true,
+                        record_type,
+                        ip: current_ip,
+                        previous_ip: last_ip,
+                        result,
+                    }
+                }));
+            }
+        }
+
         let len = tasks.len();
         let tmr = stimer!(Level::Debug; "UPDATE_DNS_RECORDS", "started={len}");
+        let mut results = vec![];
         while let Some(task) = tasks.next().await {
-            task??;
+            results.push(joined(task));
         }
         finish!(tmr, "finished={len}");
 
-        // save current IP address when update succeeds
-        self.cache.insert(CacheKey::LastIP, Cached::IP(current_ip));
+        for result in &results {
+            let (name, record_type, ip) = (&result.name, result.record_type, result.ip);
+            match &result.result {
+                Ok(record) => match record.action {
+                    Action::Create => info!("{name} ({record_type}) created with {ip}"),
+                    Action::Update => info!("{name} ({record_type}) updated to {ip}"),
+                    Action::Skip => info!("{name} ({record_type}) unchanged, {ip} already"),
+                },
+                Err(e) => warn!("{name} ({record_type}) failed to publish {ip}: {e:#}"),
+            }
+        }
+
+        let resolved_records: Vec<&ResolvedRecord> = results
+            .iter()
+            .filter_map(|r| r.result.as_ref().ok())
+            .collect();
+        for record in &resolved_records {
+            let key = CacheKey::RecordID(record.name.clone(), RecordType::of(&record.ip));
+            self.identifiers
+                .insert(key, Cached::Identifier(record.id.clone()));
+        }
+
+        // save current IP addresses when all DNS records of the type succeed,
+        // so failed DNS records are retried in next run
+        let published: Vec<IpAddr> = current_ips
+            .into_iter()
+            .filter(|ip| {
+                let record_type = RecordType::of(ip);
+                !results
+                    .iter()
+                    .any(|r| r.record_type == record_type && r.result.is_err())
+            })
+            .collect();
+        for current_ip in &published {
+            match *current_ip {
+                IpAddr::V4(i) => self.cache.insert(CacheKey::LastIPv4, Cached::IPv4(i)),
+                IpAddr::V6(i) => self.cache.insert(CacheKey::LastIPv6, Cached::IPv6(i)),
+            }
+        }
+
+        // keep IP addresses per DNS record, so DNS records which succeed are skipped on retry
+        let state = {
+            let mut state = self.state.lock().expect("state lock poisoned");
+            state.zone = self.zone.to_string();
+            state.zone_id = Some(zone_id);
+            for current_ip in published {
+                state
+                    .last_ips
+                    .insert(RecordType::of(&current_ip), current_ip);
+            }
+            for record in resolved_records {
+                state.upsert_record(RecordState {
+                    name: record.name.clone(),
+                    record_type: RecordType::of(&record.ip),
+                    id: record.id.clone(),
+                    ip: record.ip,
+                });
+            }
+            state.clone()
+        };
+        if let Some(state_file) = self.state_file.clone() {
+            // writing and syncing file would block the async runtime
+            let saved = tokio::task::spawn_blocking(move || state.save(state_file)).await;
+            joined(saved).map_err(|e| Error::State(e.into()))?;
+        }
 
-        Ok(())
+        Ok(results)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use futures::future::BoxFuture;
+
     use super::*;
 
-    use mockito::{mock, Matcher};
-    use std::sync::Arc;
+    use crate::fake::{FakeProvider, ZONE_ID};
+
+    fn record() -> Record {
+        Record {
+            id: "2".into(),
+            name: "record".into(),
+            content: "127.0.0.1".into(),
+            ttl: 300,
+            proxied: Some(true),
+        }
+    }
+
+    #[test]
+    fn t_records() {
+        let cdu = Cdu::new(
+            "token",
+            "zone",
+            &["a", "b:AAAA", "c:a", "d:MX", "glob:*.x.com:AAAA"],
+        )
+        .with_record_types(&[RecordType::A, RecordType::AAAA]);
+        let records: Vec<(String, RecordType)> = cdu
+            .records()
+            .unwrap()
+            .into_iter()
+            .map(|(s, t)| (s.to_string(), t))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a".to_string(), RecordType::A),
+                ("a".to_string(), RecordType::AAAA),
+                ("b".to_string(), RecordType::AAAA),
+                ("c".to_string(), RecordType::A),
+                ("d:MX".to_string(), RecordType::A),
+                ("d:MX".to_string(), RecordType::AAAA),
+                ("glob:*.x.com".to_string(), RecordType::AAAA),
+            ],
+            records
+        );
+
+        let cdu = Cdu::new("token", "zone", &["regex:("]);
+        let e = cdu.records().unwrap_err();
+        assert!(matches!(e, Error::InvalidRecord { .. }));
+
+        let e = "MX".parse::<RecordType>().unwrap_err();
+        assert_eq!("unsupported record type: MX", e.to_string());
+    }
 
     #[tokio::test]
-    async fn t_get_record_identifier() {
-        let _m = mock("GET", "/client/v4/zones/1/dns_records")
-            .match_query(Matcher::UrlEncoded("name".into(), "record".into()))
-            .with_status(200)
-            .with_body(r#"{"success":true,"result":[{"meta":{"auto_added":false},"locked":false,"name":"record","ttl":0,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"0.0.0.0","type":"A","id":"2","proxied":false,"zone_name":"zone"}],"messages":[],"errors":[]}"#)
-            .create();
-        let cdu = Cdu::new("token", "zone", &["record"]);
-        let agent = Arc::new(cdu.build_agent());
-        let (id, record_name) = get_record_identifier(agent.clone(), "token", "1", "record")
-            .await
+    async fn t_expand() {
+        let listed = ["a.home.x.com", "b.home.x.com", "www.x.com"]
+            .iter()
+            .map(|name| Record {
+                name: name.to_string(),
+                ..record()
+            })
+            .collect();
+        let cdu = Cdu::new(
+            "token",
+            "x.com",
+            &[
+                "www.x.com",
+                "glob:*.home.x.com",
+                r"regex:^www\.",
+                "glob:*.y.com",
+            ],
+        )
+        .with_provider(Arc::new(FakeProvider::new(listed)));
+        let records = cdu.records().unwrap();
+        let expanded = cdu.expand("1", records).await.unwrap();
+        assert_eq!(
+            vec![
+                ("www.x.com".to_string(), RecordType::A),
+                ("a.home.x.com".to_string(), RecordType::A),
+                ("b.home.x.com".to_string(), RecordType::A),
+            ],
+            expanded
+        );
+    }
+
+    #[test]
+    fn t_with_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(
+            &path,
+            r#"{"zone":"zone","zone_id":"1","last_ips":{"A":"127.0.0.1"},"records":[{"name":"a","type":"A","id":"2","ip":"127.0.0.1"},{"name":"b","type":"A","id":"3","ip":"127.0.0.1"},{"name":"a","type":"AAAA","id":"4","ip":"::1"}]}"#,
+        )
+        .unwrap();
+
+        let cdu = Cdu::new("token", "zone", &["a", "b"])
+            .with_record_types(&[RecordType::A, RecordType::AAAA])
+            .with_state_file(&path)
+            .unwrap();
+        let zone_id = cdu.identifiers.get(&CacheKey::ZoneID).unwrap();
+        assert_eq!(Some("1".to_string()), zone_id.identifier());
+        let record_id = cdu
+            .identifiers
+            .get(&CacheKey::RecordID("a".into(), RecordType::AAAA))
             .unwrap();
-        assert_eq!("2", id);
-        assert_eq!("record", record_name);
+        assert_eq!(Some("4".to_string()), record_id.identifier());
+        let last_ipv4 = cdu.cache.get(&CacheKey::LastIPv4).unwrap();
+        assert_eq!(Some("127.0.0.1".parse().unwrap()), last_ipv4.ip());
+        // AAAA records were not all published, e.g. AAAA record of b failed
+        assert!(cdu.cache.get(&CacheKey::LastIPv6).is_none());
+
+        let cdu = Cdu::new("token", "other", &["a", "b"])
+            .with_state_file(&path)
+            .unwrap();
+        assert!(cdu.cache.get(&CacheKey::LastIPv4).is_none());
     }
 
     #[tokio::test]
-    async fn t_get_zone_identifier() {
-        let _m = mock("GET", "/client/v4/zones")
-            .match_query(Matcher::UrlEncoded("name".into(), "zone".into()))
-            .with_status(200)
-            .with_body(r#"{"success":true,"result":[{"id":"1","name":"zone","account":{"id":"2","name":"a"},"created_on":"1970-01-01T00:00:00Z","development_mode":0,"meta":{"custom_certificate_quota":0,"page_rule_quota":0,"phishing_detected":false,"multiple_railguns_allowed":false},"modified_on":"1970-01-01T00:00:00Z","name_servers":[],"owner":{"type":"user","email":"","id":""},"paused":false,"permissions":[],"status":"active","type":"full"}],"messages":[],"errors":[]}"#)
-            .create();
-        let cdu = Cdu::new("token", "zone", &["record"]);
-        let agent = Arc::new(cdu.build_agent());
-        let zone_identifier = cdu.get_zone_identifier(agent.clone()).await.unwrap();
-        assert_eq!(zone_identifier, "1");
+    async fn t_state_file_of_other_zone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(
+            &path,
+            r#"{"zone":"zone","zone_id":"1","records":[{"name":"a","type":"A","id":"2","ip":"1.1.1.1"}]}"#,
+        )
+        .unwrap();
+
+        let provider = Arc::new(FakeProvider::new(vec![Record {
+            id: "5".into(),
+            name: "a".into(),
+            content: "1.1.1.1".into(),
+            ..record()
+        }]));
+        let cdu = Cdu::from_provider(provider, "other", &["a"])
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())))
+            .with_state_file(&path)
+            .unwrap();
+        assert!(cdu.identifiers.get(&CacheKey::ZoneID).is_none());
+        assert_eq!(None, cdu.last_ip("a", RecordType::A));
+
+        // DNS record is checked rather than skipped as holding the IP address of the other zone
+        let report = cdu.run().await.unwrap();
+        assert_eq!(RecordOutcome::Unchanged, report.records[0].outcome);
+        let state = State::load(&path).unwrap();
+        assert_eq!("other", state.zone);
+        assert_eq!(
+            Some(&"1.1.1.1".parse::<IpAddr>().unwrap()),
+            state.last_ips.get(&RecordType::A)
+        );
+        assert_eq!(
+            vec!["5"],
+            state
+                .records
+                .iter()
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// DNS records of x.com holding 1.1.1.2
+    fn records(names: &[&str]) -> Vec<Record> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Record {
+                id: (i + 1).to_string(),
+                name: name.to_string(),
+                content: "1.1.1.2".into(),
+                ..record()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn t_run_partial_failure() {
+        let provider = Arc::new(
+            FakeProvider::new(records(&["a.x.com", "b.x.com"])).with_failures(vec![Error::Api {
+                status: Some(400),
+                message: "HTTP 400 Bad Request".into(),
+                retry_after: None,
+            }]),
+        );
+        let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com", "b.x.com"])
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())));
+
+        // the other DNS record is updated although one fails
+        let report = cdu.run().await.unwrap();
+        assert!(!report.is_success());
+        assert_eq!(2, provider.writes());
+        let updated: Vec<&RecordReport> = report
+            .records
+            .iter()
+            .filter(|r| r.outcome == RecordOutcome::Updated)
+            .collect();
+        let failed = report.failures();
+        assert_eq!(1, updated.len());
+        assert_eq!(1, failed.len());
+        assert_ne!(updated[0].name, failed[0].name);
+        assert_eq!(
+            Some("DNS provider fails: HTTP 400 Bad Request"),
+            failed[0].error.as_deref()
+        );
+        let mut contents: Vec<String> = provider.records().into_iter().map(|r| r.content).collect();
+        contents.sort();
+        assert_eq!(vec!["1.1.1.1", "1.1.1.2"], contents);
+
+        // only the failed DNS record is updated in next run
+        let failed_name = failed[0].name.clone();
+        let report = cdu.run().await.unwrap();
+        assert!(report.is_success());
+        assert_eq!(3, provider.writes());
+        for r in &report.records {
+            let expected = if r.name == failed_name {
+                RecordOutcome::Updated
+            } else {
+                RecordOutcome::Unchanged
+            };
+            assert_eq!(expected, r.outcome, "{r}");
+        }
+        assert!(provider.records().iter().all(|r| r.content == "1.1.1.1"));
+    }
+
+    fn identifier(cdu: &Cdu, key: CacheKey) -> Option<String> {
+        cdu.identifiers.get(&key).and_then(Cached::identifier)
+    }
+
+    #[tokio::test]
+    async fn t_run_stale_record_id() {
+        let provider = Arc::new(FakeProvider::new(records(&["a.x.com"])).with_failures(vec![
+            Error::RecordNotFound {
+                name: "a.x.com".into(),
+                record_type: RecordType::A,
+            },
+        ]));
+        let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com"])
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())));
+        let key = CacheKey::RecordID("a.x.com".into(), RecordType::A);
+        cdu.identifiers
+            .insert(CacheKey::ZoneID, Cached::Identifier(ZONE_ID.into()));
+        cdu.identifiers
+            .insert(key.clone(), Cached::Identifier("99".into()));
+
+        // record identifier is looked up again after the provider does not find the record
+        let report = cdu.run().await.unwrap();
+        assert_eq!(RecordOutcome::Updated, report.records[0].outcome);
+        assert_eq!(2, provider.writes());
+        assert_eq!(Some("1".to_string()), identifier(&cdu, key));
+        assert_eq!("1.1.1.1", provider.records()[0].content);
+    }
+
+    #[tokio::test]
+    async fn t_run_unchanged() {
+        let provider = Arc::new(FakeProvider::new(records(&["a.x.com", "b.x.com"])));
+        let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com", "b.x.com"])
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.2".parse().unwrap())));
+        // IP address changed since last run, record identifier of a.x.com is cached
+        cdu.cache
+            .insert(CacheKey::LastIPv4, Cached::IPv4("1.1.1.1".parse().unwrap()));
+        cdu.identifiers.insert(
+            CacheKey::RecordID("a.x.com".into(), RecordType::A),
+            Cached::Identifier("1".into()),
+        );
+
+        // DNS records holding the IP address already are not updated
+        let report = cdu.run().await.unwrap();
+        assert_eq!(
+            vec![
+                "a.x.com (A): unchanged 1.1.1.2",
+                "b.x.com (A): unchanged 1.1.1.2",
+            ],
+            report
+                .records
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(0, provider.writes());
+    }
+
+    #[tokio::test]
+    async fn t_run_stale_zone_id() {
+        let provider = Arc::new(FakeProvider::new(records(&["a.x.com"])));
+        let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com"])
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())));
+        cdu.identifiers
+            .insert(CacheKey::ZoneID, Cached::Identifier("99".into()));
+
+        // zone identifier is looked up again after the provider does not find the zone
+        let report = cdu.run().await.unwrap();
+        assert!(report.is_success());
+        assert_eq!(RecordOutcome::Updated, report.records[0].outcome);
+        assert_eq!(
+            Some(ZONE_ID.to_string()),
+            identifier(&cdu, CacheKey::ZoneID)
+        );
+        assert_eq!("1.1.1.1", provider.records()[0].content);
     }
 
     #[tokio::test]
-    async fn t_update_dns_record() {
-        let _m2 = mock("PUT", "/client/v4/zones/1/dns_records/2")
-            .match_body(r#"{"content":"127.0.0.1","name":"record","ttl":1,"type":"A"}"#)
-            .with_status(200)
-            .with_body(r#"{"success":true,"result":{"meta":{"auto_added":false},"locked":false,"name":"record","ttl":0,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"0.0.0.0","type":"A","id":"2","proxied":false,"zone_name":"zone"},"messages":[],"errors":[]}"#)
-            .create();
+    async fn t_run_no_quorum() {
+        let sources: Vec<Arc<dyn IpSource>> = vec![
+            Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())),
+            Arc::new(StaticIpSource::new("1.1.1.2".parse().unwrap())),
+        ];
+        let provider = Arc::new(FakeProvider::new(records(&["a.x.com"])));
+        let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com"])
+            .with_ip_source(Arc::new(QuorumIpSource::new(sources, 2).unwrap()));
+
+        let e = cdu.run().await.unwrap_err();
+        match &e {
+            Error::NoQuorum {
+                record_type,
+                votes,
+                required,
+                failures,
+            } => {
+                assert_eq!(RecordType::A, *record_type);
+                assert_eq!(
+                    vec![
+                        ("1.1.1.1".parse::<IpAddr>().unwrap(), 1),
+                        ("1.1.1.2".parse::<IpAddr>().unwrap(), 1)
+                    ],
+                    *votes
+                );
+                assert_eq!(2, *required);
+                assert_eq!(0, *failures);
+            }
+            e => panic!("unexpected error: {e}"),
+        }
+        assert_eq!(Some(Retry::Backoff), e.retry());
+        assert_eq!(0, provider.writes());
+    }
+
+    #[test]
+    fn t_with_identifier_ttl() {
         let cdu = Cdu::new("token", "zone", &["record"]);
-        let agent = Arc::new(cdu.build_agent());
-        update_dns_record(
-            agent.clone(),
-            "token",
-            "1",
-            "2",
-            "record",
+        cdu.identifiers
+            .insert(CacheKey::ZoneID, Cached::Identifier("1".into()));
+        let cdu = cdu.with_identifier_ttl(Duration::from_secs(60));
+        let zone_id = cdu.identifiers.get(&CacheKey::ZoneID).unwrap();
+        assert_eq!(Some("1".to_string()), zone_id.identifier());
+    }
+
+    #[test]
+    fn t_options_of() {
+        let cdu = Cdu::new("token", "zone", &["a", "b"])
+            .with_record_options(RecordOptions {
+                ttl: Some(300),
+                proxied: None,
+            })
+            .with_options_for(
+                "b",
+                RecordOptions {
+                    ttl: None,
+                    proxied: Some(true),
+                },
+            )
+            .with_options_for(
+                "b",
+                RecordOptions {
+                    ttl: Some(60),
+                    proxied: None,
+                },
+            );
+        assert_eq!(
+            RecordOptions {
+                ttl: Some(300),
+                proxied: None
+            },
+            cdu.options_of("a")
+        );
+        assert_eq!(
+            RecordOptions {
+                ttl: Some(60),
+                proxied: Some(true)
+            },
+            cdu.options_of("b")
+        );
+    }
+
+    #[test]
+    fn t_is_up_to_date() {
+        let record = record();
+        let options = RecordOptions::default();
+        assert!(is_up_to_date(
+            &record,
             "127.0.0.1".parse().unwrap(),
-        )
-        .await
-        .unwrap();
+            options
+        ));
+        assert!(!is_up_to_date(
+            &record,
+            "127.0.0.2".parse().unwrap(),
+            options
+        ));
+        assert!(!is_up_to_date(&record, "::1".parse().unwrap(), options));
+
+        let options = RecordOptions {
+            ttl: Some(300),
+            proxied: Some(true),
+        };
+        assert!(is_up_to_date(
+            &record,
+            "127.0.0.1".parse().unwrap(),
+            options
+        ));
+        let options = RecordOptions {
+            ttl: Some(1),
+            proxied: None,
+        };
+        assert!(!is_up_to_date(
+            &record,
+            "127.0.0.1".parse().unwrap(),
+            options
+        ));
+        let options = RecordOptions {
+            ttl: None,
+            proxied: Some(false),
+        };
+        assert!(!is_up_to_date(
+            &record,
+            "127.0.0.1".parse().unwrap(),
+            options
+        ));
+    }
+
+    #[test]
+    fn t_validate_ip() {
+        let cdu = Cdu::new("token", "zone", &["record"]);
+        assert!(cdu.validate_ip("1.1.1.1".parse().unwrap()).is_ok());
+        let e = cdu.validate_ip("100.64.1.1".parse().unwrap()).unwrap_err();
+        assert_eq!("CGNAT", e.range);
+        assert!(cdu.validate_ip("fd00::1".parse().unwrap()).is_err());
+        let e = cdu
+            .validate_ip("64:ff9b::101:101".parse().unwrap())
+            .unwrap_err();
+        assert_eq!("NAT64", e.range);
+        // Teredo
+        let e = cdu
+            .validate_ip("2001:0:4136:e378::1".parse().unwrap())
+            .unwrap_err();
+        assert_eq!("IETF protocol assignments", e.range);
+        let e = cdu
+            .validate_ip("2002:101:101::1".parse().unwrap())
+            .unwrap_err();
+        assert_eq!("6to4", e.range);
+        assert!(cdu
+            .validate_ip("2606:4700:4700::1111".parse().unwrap())
+            .is_ok());
+
+        let cdu = cdu.with_allowed_networks(&["10.0.0.0/8".parse().unwrap()]);
+        assert!(cdu.validate_ip("10.1.2.3".parse().unwrap()).is_ok());
+        assert!(cdu.validate_ip("192.168.1.1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn t_plan_change() {
+        let options = RecordOptions {
+            ttl: Some(60),
+            proxied: Some(true),
+        };
+
+        let change = plan_change(
+            Some(record()),
+            "record".into(),
+            "127.0.0.2".parse().unwrap(),
+            options,
+            None,
+        );
+        assert_eq!(PlannedAction::Update, change.action);
+        assert_eq!(Some("127.0.0.1".to_string()), change.current_content);
+        assert_eq!(Some((Some(300), 60)), change.ttl);
+        assert_eq!(None, change.proxied);
+
+        let change = plan_change(
+            Some(record()),
+            "record".into(),
+            "127.0.0.1".parse().unwrap(),
+            RecordOptions::default(),
+            None,
+        );
+        assert_eq!(PlannedAction::Unchanged, change.action);
+
+        let change = plan_change(
+            None,
+            "record".into(),
+            "127.0.0.1".parse().unwrap(),
+            options,
+            Some(CreateOptions::default()),
+        );
+        assert_eq!(PlannedAction::Create, change.action);
+        assert_eq!(Some((None, 60)), change.ttl);
+        assert_eq!(Some((None, true)), change.proxied);
+
+        let change = plan_change(
+            None,
+            "record".into(),
+            "127.0.0.1".parse().unwrap(),
+            options,
+            None,
+        );
+        assert_eq!(PlannedAction::Missing, change.action);
+    }
+
+    /// IP list holding items in memory
+    #[derive(Debug, Default)]
+    struct MemoryIpList(Mutex<Vec<ListItem>>);
+
+    impl MemoryIpList {
+        fn ips(&self) -> Vec<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|i| i.ip.clone())
+                .collect()
+        }
+    }
+
+    impl IpList for MemoryIpList {
+        fn name(&self) -> &str {
+            "office"
+        }
+
+        fn comment(&self) -> &str {
+            "cdu"
+        }
+
+        fn items(&self) -> BoxFuture<'_, Result<Vec<ListItem>>> {
+            Box::pin(async move { Ok(self.0.lock().unwrap().clone()) })
+        }
+
+        fn add_item(&self, ip: IpAddr) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                let mut items = self.0.lock().unwrap();
+                let id = format!("{}", items.len() + 1);
+                items.push(ListItem {
+                    id,
+                    ip: ip.to_string(),
+                    comment: Some("cdu".into()),
+                });
+                Ok(())
+            })
+        }
+
+        fn remove_items<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                self.0.lock().unwrap().retain(|i| !ids.contains(&i.id));
+                Ok(())
+            })
+        }
+    }
+
+    fn item(id: &str, ip: &str, comment: Option<&str>) -> ListItem {
+        ListItem {
+            id: id.into(),
+            ip: ip.into(),
+            comment: comment.map(String::from),
+        }
+    }
+
+    #[test]
+    fn t_list_sync() {
+        let list = MemoryIpList::default();
+        let items = vec![
+            item("1", "1.1.1.1", Some("cdu")),
+            item("2", "2.2.2.2", None),
+            item("3", "3.3.3.0/24", Some("cdu")),
+            item("4", "2001:db8::/64", Some("cdu")),
+            item("5", "4.4.4.4", Some("another")),
+        ];
+        let ip = "5.5.5.5".parse().unwrap();
+
+        // items added by cdu and the last IP address are replaced, others are kept
+        let sync = ListSync::new(&list, items.clone(), ip, "2.2.2.2".parse().ok());
+        assert!(sync.current.is_none());
+        let stale: Vec<&str> = sync.stale.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(vec!["1", "2", "3"], stale);
+        assert_eq!(Action::Update, sync.action());
+        assert_eq!(
+            Some("1.1.1.1,2.2.2.2,3.3.3.0/24"),
+            sync.content().as_deref()
+        );
+
+        let sync = ListSync::new(&list, items[3..].to_vec(), ip, None);
+        assert_eq!(Action::Create, sync.action());
+        assert_eq!(None, sync.content());
+
+        let ip = "1.1.1.1".parse().unwrap();
+        let sync = ListSync::new(&list, items[..2].to_vec(), ip, None);
+        assert_eq!(Action::Skip, sync.action());
+        assert_eq!(Some("1.1.1.1"), sync.content().as_deref());
+    }
+
+    #[tokio::test]
+    async fn t_run_ip_list() {
+        let list = Arc::new(MemoryIpList::default());
+        list.0
+            .lock()
+            .unwrap()
+            .push(item("0", "9.9.9.9", Some("manual")));
+        let records: [&str; 0] = [];
+        let cdu = Cdu::from_provider(Arc::new(FakeProvider::default()), "x.com", &records)
+            .with_ip_list(list.clone())
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())));
+        let report = cdu.run().await.unwrap();
+        assert_eq!(
+            vec!["list:office (A): created 1.1.1.1"],
+            report
+                .lists
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(vec!["9.9.9.9", "1.1.1.1"], list.ips());
+
+        // skipped while IP address remains unchanged
+        assert!(cdu.run().await.unwrap().lists.is_empty());
+
+        let cdu = cdu.with_ip_source(Arc::new(StaticIpSource::new("1.1.1.2".parse().unwrap())));
+        let report = cdu.run().await.unwrap();
+        assert_eq!(
+            "list:office (A): updated 1.1.1.1 -> 1.1.1.2",
+            report.lists[0].to_string()
+        );
+        // IP lists are no DNS records changing IP address
+        assert!(report.records.is_empty());
+        assert!(report.changes().is_empty());
+        assert_eq!(vec!["9.9.9.9", "1.1.1.2"], list.ips());
     }
 }
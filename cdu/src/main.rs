@@ -13,37 +13,437 @@
 //! Cloudflare DNS record update
 
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::thread;
+use std::sync::Arc;
 use std::time::Duration;
 
-use clap::Parser;
-use cloudflare::framework::response::ApiFailure;
+use anyhow::Context as _;
+use clap::{Parser, ValueEnum};
 use cron::Schedule;
-use log::{debug, info, warn, Level};
+use log::{debug, error, info, warn, Level};
 use logging_timer::{finish, timer};
+use pushover::Priority;
+use tokio::sync::mpsc;
 
-use cdu::{Cdu, NoIPV4};
+use cdu::{
+    parse_ip_source, ApiClient, Cdu, CloudflareProvider, CreateOptions, Credentials, IpNet,
+    IpSource, RecordInfo, RecordOptions, RecordType, Retry, RunReport, StaticIpSource, TokenStatus,
+    ZoneInfo,
+};
+
+use crate::config::{cloudflare_credentials, Config, IpListConfig, Rfc2136Config, ZoneConfig};
+use crate::history::{History, HistoryEntry, HistoryFilter};
+use crate::hook::{HookEvent, Hooks};
+use crate::metrics::{MeasuredProvider, Metrics};
+use crate::notify::Notifier;
+
+mod config;
+mod history;
+mod hook;
+mod metrics;
+#[cfg(target_os = "linux")]
+mod netlink;
+mod notify;
+
+/// Output format of run reports
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text
+    Text,
+    /// One JSON object per zone and line
+    Json,
+}
+
+/// Subcommands besides updating DNS records
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// List IP address changes in history file, oldest first
+    History {
+        /// Conditions on listed changes
+        #[command(flatten)]
+        filter: HistoryFilter,
+        /// List only the last N changes
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Publish the IP address held before a change in history file to the DNS records
+    /// of the change, with zone settings from command line or configuration file
+    Rollback {
+        /// Number of the change as listed by `history`
+        number: usize,
+    },
+    /// Verify Cloudflare token, print its status and permissions
+    Verify,
+    /// List zones accessible to Cloudflare token
+    Zones,
+    /// List DNS records of all types in zone
+    Records {
+        /// Zone name e.g. x.com
+        zone: String,
+    },
+}
 
 /// Argument parser
 #[derive(Debug, Parser)]
-#[command(about, author, version)]
+#[command(about, author, version, subcommand_negates_reqs = true)]
 pub struct Opts {
+    /// Subcommand, update DNS records if not given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Configuration file in TOML with multiple zones,
+    /// which take place of zone settings from command line
+    #[arg(long, env = "CONFIG")]
+    pub config: Option<PathBuf>,
     /// Cloudflare token
-    #[arg(short, long, env = "CLOUDFLARE_TOKEN")]
-    pub token: String,
+    #[arg(
+        short,
+        long,
+        env = "CLOUDFLARE_TOKEN",
+        required_unless_present_any = ["config", "rfc2136_server", "token_file", "email"],
+        conflicts_with = "rfc2136_server"
+    )]
+    pub token: Option<String>,
+    /// File holding Cloudflare token e.g. a Docker or Kubernetes secret, in place of --token
+    #[arg(long, env = "CLOUDFLARE_TOKEN_FILE", conflicts_with_all = ["token", "rfc2136_server"])]
+    pub token_file: Option<PathBuf>,
+    /// Email of Cloudflare account, to authenticate with global API key instead of token
+    #[arg(
+        long,
+        env = "CLOUDFLARE_EMAIL",
+        conflicts_with_all = ["token", "token_file", "rfc2136_server"]
+    )]
+    pub email: Option<String>,
+    /// Cloudflare global API key, requires --email
+    #[arg(long, env = "CLOUDFLARE_API_KEY", requires = "email")]
+    pub api_key: Option<String>,
+    /// File holding Cloudflare global API key, in place of --api-key
+    #[arg(
+        long,
+        env = "CLOUDFLARE_API_KEY_FILE",
+        requires = "email",
+        conflicts_with = "api_key"
+    )]
+    pub api_key_file: Option<PathBuf>,
+    /// Update DNS records with RFC 2136 dynamic updates on the primary server
+    /// e.g. 192.0.2.1:53 instead of Cloudflare
+    #[arg(long, env = "RFC2136_SERVER", requires_all = ["tsig_key_name", "tsig_secret"])]
+    pub rfc2136_server: Option<SocketAddr>,
+    /// Name of TSIG key to sign RFC 2136 dynamic updates
+    #[arg(long, env = "TSIG_KEY_NAME")]
+    pub tsig_key_name: Option<String>,
+    /// TSIG algorithm, one of hmac-sha256, hmac-sha384 and hmac-sha512
+    #[arg(long, default_value = "hmac-sha256", env = "TSIG_ALGORITHM")]
+    pub tsig_algorithm: String,
+    /// TSIG secret encoded in base64
+    #[arg(long, env = "TSIG_SECRET")]
+    pub tsig_secret: Option<String>,
     /// Cloudflare zone name
-    #[arg(short, long, env = "CLOUDFLARE_ZONE")]
-    pub zone: String,
-    /// Cloudflare records separated with comma e.g. a.x.com,b.x.com
-    #[arg(short, long, env = "CLOUDFLARE_RECORDS")]
-    pub records: String,
+    #[arg(
+        short,
+        long,
+        env = "CLOUDFLARE_ZONE",
+        required_unless_present = "config"
+    )]
+    pub zone: Option<String>,
+    /// Cloudflare records separated with comma e.g. a.x.com,b.x.com.
+    /// Record type can be specified per record e.g. a.x.com:AAAA.
+    /// Records of the zone can be selected with glob e.g. glob:*.home.x.com
+    /// or regular expression e.g. regex:^(a|b)\.x\.com$
+    #[arg(
+        short,
+        long,
+        env = "CLOUDFLARE_RECORDS",
+        required_unless_present = "config"
+    )]
+    pub records: Option<String>,
+    /// Record types to update separated with comma e.g. A,AAAA.
+    /// A records are updated with IPv4 address, AAAA records with IPv6 address
+    #[arg(
+        long,
+        default_value = "A",
+        env = "CLOUDFLARE_RECORD_TYPES",
+        value_delimiter = ','
+    )]
+    pub record_types: Vec<RecordType>,
+    /// Source of IP address, one of `public`, `url=<URL>`, `interface=<NAME>` or `command=<COMMAND>`.
+    /// URL returns IP address in plain text or JSON.
+    /// Command prints IP address, with record type in `CDU_RECORD_TYPE`.
+    /// Specify multiple times to query sources concurrently and require a quorum
+    #[arg(long, default_value = "public", env = "IP_SOURCE", value_parser = parse_ip_source)]
+    pub ip_source: Vec<Arc<dyn IpSource>>,
+    /// Number of IP address sources required to agree, defaults to majority of them
+    #[arg(long, env = "QUORUM")]
+    pub quorum: Option<usize>,
+    /// Networks separated with comma allowed to publish though private, CGNAT or reserved
+    /// e.g. 10.0.0.0/8,fd00::/8 for split-horizon DNS
+    #[arg(long, env = "ALLOWED_NETWORKS", value_delimiter = ',')]
+    pub allowed_networks: Vec<IpNet>,
+    /// Cloudflare IP lists separated with comma e.g. <ACCOUNT_ID>/office to keep holding
+    /// the published IP addresses, in place of previous ones
+    #[arg(long, env = "IP_LISTS", value_delimiter = ',', value_parser = parse_ip_list)]
+    pub ip_lists: Vec<(String, String)>,
+    /// Comment of IP list items added, which are replaced on change.
+    /// Instances sharing an IP list need distinct comments
+    #[arg(long, default_value = cdu::DEFAULT_LIST_ITEM_COMMENT, env = "IP_LIST_COMMENT")]
+    pub ip_list_comment: String,
+    /// Time to live of all DNS records to set on update, 1 for automatic.
+    /// Keep current time to live if not specified
+    #[arg(long, env = "TTL")]
+    pub ttl: Option<u32>,
+    /// Whether all DNS records are proxied by Cloudflare to set on update.
+    /// Keep current setting if not specified
+    #[arg(long, env = "PROXIED")]
+    pub proxied: Option<bool>,
+    /// Time to live per record separated with comma e.g. a.x.com=300,b.x.com=1
+    #[arg(long, env = "RECORD_TTL", value_delimiter = ',', value_parser = parse_key_value::<u32>)]
+    pub record_ttl: Vec<(String, u32)>,
+    /// Whether proxied by Cloudflare per record separated with comma e.g. a.x.com=true,b.x.com=false
+    #[arg(long, env = "RECORD_PROXIED", value_delimiter = ',', value_parser = parse_key_value::<bool>)]
+    pub record_proxied: Vec<(String, bool)>,
+    /// Create DNS records which do not exist
+    #[arg(long, env = "CREATE_MISSING", action = clap::ArgAction::SetTrue)]
+    pub create_missing: bool,
+    /// Time to live of created DNS records, 1 for automatic.
+    /// Only in effect when creating missing records
+    #[arg(long, default_value = "1", env = "CREATE_TTL")]
+    pub create_ttl: u32,
+    /// Whether created DNS records are proxied by Cloudflare.
+    /// Only in effect when creating missing records
+    #[arg(long, env = "CREATE_PROXIED", action = clap::ArgAction::SetTrue)]
+    pub create_proxied: bool,
+    /// Base URL of Cloudflare API
+    #[arg(long, default_value = cdu::DEFAULT_API_URL, env = "CLOUDFLARE_API_URL")]
+    pub api_url: String,
+    /// Timeout in seconds of each request to Cloudflare API
+    #[arg(long, default_value = "30", env = "API_TIMEOUT")]
+    pub api_timeout: u64,
+    /// Time span in seconds to cache zone and record identifiers
+    #[arg(long, default_value = "86400", env = "IDENTIFIER_TTL")]
+    pub identifier_ttl: u64,
+    /// File to persist last published IP addresses, zone and record identifiers
+    #[arg(long, env = "STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+    /// File to append IP address changes to in JSON Lines, listed by `history` subcommand
+    #[arg(long, env = "HISTORY_FILE")]
+    pub history_file: Option<PathBuf>,
+    /// Print planned changes without updating DNS records,
+    /// exit with status 2 if any DNS record would change
+    #[arg(long, env = "DRY_RUN", action = clap::ArgAction::SetTrue, conflicts_with = "daemon")]
+    pub dry_run: bool,
+    /// Number of retries when fetching IP address fails, or DNS provider rate limits requests
+    /// or responds with server error
+    #[arg(long, default_value = "10", env = "RETRY_ATTEMPTS")]
+    pub retry_attempts: u32,
+    /// Minimum delay in milliseconds of exponential backoff between retries
+    #[arg(long, default_value = "100", env = "RETRY_MIN_DELAY")]
+    pub retry_min_delay: u64,
+    /// Maximum delay in milliseconds between retries. Stop retrying if DNS provider asks
+    /// with `Retry-After` to wait longer
+    #[arg(long, default_value = "10000", env = "RETRY_MAX_DELAY")]
+    pub retry_max_delay: u64,
+    /// Output format of run reports and subcommands, one of text and json.
+    /// In daemon mode, only reports of zones whose IP addresses change are printed
+    #[arg(long, value_enum, default_value = "text", env = "OUTPUT")]
+    pub output: OutputFormat,
     /// Daemon mode
     #[arg(short, long, env = "DAEMON", action = clap::ArgAction::SetTrue)]
     pub daemon: bool,
     /// Cron. Only in effect in daemon mode
     #[arg(short, long, default_value = "0 */5 * * * * *", env = "CRON")]
     pub cron: String,
+    /// Network interface e.g. ppp0 to watch on Linux, update DNS records as soon as
+    /// it gains an IP address besides cron. Only in effect in daemon mode
+    #[arg(long, env = "WATCH_INTERFACE", requires = "daemon")]
+    pub watch_interface: Option<String>,
+    /// Address to serve `/metrics` and `/healthz` over HTTP e.g. 0.0.0.0:9090.
+    /// Only in effect in daemon mode
+    #[arg(long, env = "LISTEN", requires = "daemon")]
+    pub listen: Option<SocketAddr>,
+    /// Number of failures in a row after which a zone is reported unhealthy on `/healthz`
+    #[arg(long, default_value = "3", env = "UNHEALTHY_AFTER")]
+    pub unhealthy_after: u64,
+    /// Shell command to run after DNS records change IP address e.g. to update firewall rules,
+    /// with zone, record type, old and new IP addresses and record names separated with comma
+    /// in `CDU_ZONE`, `CDU_RECORD_TYPE`, `CDU_OLD_IP`, `CDU_NEW_IP` and `CDU_RECORDS`.
+    /// Specify multiple times to run commands in order
+    #[arg(long, env = "HOOK")]
+    pub hook: Vec<String>,
+    /// Timeout in seconds of each hook command
+    #[arg(long, default_value = "60", env = "HOOK_TIMEOUT")]
+    pub hook_timeout: u64,
+    /// Pushover token to notify IP address changes and update failures
+    #[arg(long, env = "PUSHOVER_TOKEN", requires = "pushover_user")]
+    pub pushover_token: Option<String>,
+    /// Pushover user or group to notify
+    #[arg(long, env = "PUSHOVER_USER", requires = "pushover_token")]
+    pub pushover_user: Option<String>,
+    /// Priority of notifications on IP address changes, one of lowest, low, normal, high and emergency
+    #[arg(long, default_value = "normal", env = "IP_CHANGE_PRIORITY")]
+    pub ip_change_priority: Priority,
+    /// Priority of notifications on update failures, one of lowest, low, normal, high and emergency
+    #[arg(long, default_value = "high", env = "FAILURE_PRIORITY")]
+    pub failure_priority: Priority,
+}
+
+fn parse_key_value<T>(s: &str) -> anyhow::Result<(String, T)>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match s.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.parse()?)),
+        None => anyhow::bail!("invalid KEY=VALUE: no `=` found in `{s}`"),
+    }
+}
+
+fn parse_ip_list(s: &str) -> anyhow::Result<(String, String)> {
+    match s.split_once('/') {
+        Some((account_id, name)) if !account_id.is_empty() && !name.is_empty() => {
+            Ok((account_id.to_string(), name.to_string()))
+        }
+        _ => anyhow::bail!("invalid IP list, expect <ACCOUNT_ID>/<NAME>: `{s}`"),
+    }
+}
+
+/// Retries of a run with exponential backoff
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct RetryPolicy {
+    attempts: u32,
+    min_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Opts {
+    /// Retry policy, minimum delay must not exceed maximum delay
+    fn retry_policy(&self) -> anyhow::Result<RetryPolicy> {
+        if self.retry_min_delay > self.retry_max_delay {
+            anyhow::bail!(
+                "minimum retry delay {}ms exceeds maximum retry delay {}ms",
+                self.retry_min_delay,
+                self.retry_max_delay
+            );
+        }
+        Ok(RetryPolicy {
+            attempts: self.retry_attempts,
+            min_delay: Duration::from_millis(self.retry_min_delay),
+            max_delay: Duration::from_millis(self.retry_max_delay),
+        })
+    }
+
+    /// Cloudflare API client shared by zones to reuse connections
+    fn api_client(&self) -> ApiClient {
+        ApiClient::default()
+            .with_base_url(&self.api_url)
+            .with_timeout(Duration::from_secs(self.api_timeout))
+    }
+
+    /// Cloudflare credentials from command line, token or global API key
+    fn credentials(&self) -> anyhow::Result<Credentials> {
+        cloudflare_credentials(
+            self.token.as_deref(),
+            self.token_file.as_deref(),
+            self.email.as_deref(),
+            self.api_key.as_deref(),
+            self.api_key_file.as_deref(),
+        )?
+        .context("token is required, set --token, --token-file or CLOUDFLARE_TOKEN")
+    }
+
+    /// Cloudflare provider with credentials from command line, for inspection subcommands
+    fn provider(&self) -> anyhow::Result<CloudflareProvider> {
+        Ok(CloudflareProvider::new(self.credentials()?).with_client(self.api_client()))
+    }
+
+    /// History file, required by `history` and `rollback` subcommands
+    fn history(&self) -> anyhow::Result<History> {
+        self.history_file
+            .as_ref()
+            .map(History::new)
+            .context("history file is required, set --history-file or HISTORY_FILE")
+    }
+
+    /// Hook commands if any is given
+    fn hooks(&self) -> Option<Hooks> {
+        (!self.hook.is_empty()).then(|| Hooks {
+            commands: self.hook.clone(),
+            timeout: Duration::from_secs(self.hook_timeout),
+        })
+    }
+
+    /// Pushover notifier if token and user are given
+    fn notifier(&self) -> Option<Notifier> {
+        Some(Notifier {
+            token: self.pushover_token.clone()?,
+            user: self.pushover_user.clone()?,
+            ip_change_priority: self.ip_change_priority,
+            failure_priority: self.failure_priority,
+        })
+    }
+
+    /// Zone settings from command line
+    fn zone_config(&self) -> anyhow::Result<ZoneConfig> {
+        let records = self.records.as_deref().context("records are required")?;
+        let mut record_options: HashMap<String, RecordOptions> = HashMap::new();
+        for (record_name, ttl) in &self.record_ttl {
+            record_options.entry(record_name.clone()).or_default().ttl = Some(*ttl);
+        }
+        for (record_name, proxied) in &self.record_proxied {
+            record_options
+                .entry(record_name.clone())
+                .or_default()
+                .proxied = Some(*proxied);
+        }
+        let rfc2136 = match self.rfc2136_server {
+            Some(server) => Some(Rfc2136Config {
+                server,
+                key_name: self
+                    .tsig_key_name
+                    .clone()
+                    .context("TSIG key name is required")?,
+                algorithm: self.tsig_algorithm.clone(),
+                secret: self
+                    .tsig_secret
+                    .clone()
+                    .context("TSIG secret is required")?,
+                ttl: None,
+            }),
+            None => None,
+        };
+        Ok(ZoneConfig {
+            token: self.token.clone(),
+            token_file: self.token_file.clone(),
+            email: self.email.clone(),
+            api_key: self.api_key.clone(),
+            api_key_file: self.api_key_file.clone(),
+            rfc2136,
+            zone: self.zone.clone().context("zone is required")?,
+            records: records.split(',').map(String::from).collect(),
+            record_types: self.record_types.clone(),
+            ip_sources: self.ip_source.clone(),
+            quorum: self.quorum,
+            allowed_networks: self.allowed_networks.clone(),
+            ttl: self.ttl,
+            proxied: self.proxied,
+            record_options,
+            create_missing: self.create_missing.then_some(CreateOptions {
+                ttl: self.create_ttl,
+                proxied: self.create_proxied,
+            }),
+            state_file: self.state_file.clone(),
+            ip_lists: self
+                .ip_lists
+                .iter()
+                .map(|(account_id, name)| IpListConfig {
+                    account_id: account_id.clone(),
+                    name: name.clone(),
+                    comment: Some(self.ip_list_comment.clone()),
+                })
+                .collect(),
+        })
+    }
 }
 
 #[tokio::main]
@@ -52,82 +452,504 @@ async fn main() -> anyhow::Result<()> {
 
     let opts: Opts = Opts::parse();
 
-    let record_names = opts
-        .records
-        .split(',')
-        .map(String::from)
-        .collect::<Vec<String>>();
+    match &opts.command {
+        Some(Command::History { filter, limit }) => {
+            return print_history(&opts.history()?, filter, *limit, opts.output)
+        }
+        Some(Command::Verify) => {
+            if let Credentials::GlobalKey { .. } = opts.credentials()? {
+                anyhow::bail!("only API tokens can be verified, not global API keys");
+            }
+            let status = opts.provider()?.verify_token().await?;
+            print_token_status(&status, opts.output)?;
+            if !status.is_active() {
+                anyhow::bail!("token is {}", status.status);
+            }
+            return Ok(());
+        }
+        Some(Command::Zones) => {
+            let zones = opts.provider()?.zones().await?;
+            return print_zones(&zones, opts.output);
+        }
+        Some(Command::Records { zone }) => {
+            let records = opts.provider()?.records(zone).await?;
+            return print_records(&records, opts.output);
+        }
+        Some(Command::Rollback { .. }) | None => {}
+    }
+
+    let mut zones = match &opts.config {
+        Some(path) => Config::load(path)?.zones,
+        None => vec![opts.zone_config()?],
+    };
+    if let Some(Command::Rollback { number }) = &opts.command {
+        if opts.daemon {
+            anyhow::bail!("rollback cannot run as daemon, which publishes detected IP addresses");
+        }
+        let entry = opts.history()?.entry(*number)?;
+        info!("roll back {entry}");
+        zones = vec![rollback_zone(zones, &entry)?];
+    }
+    let identifier_ttl = Duration::from_secs(opts.identifier_ttl);
+    let client = opts.api_client();
+    let metrics = Arc::new(Metrics::default());
+    let cdus = zones
+        .iter()
+        .map(|z| {
+            let cdu = z.build(identifier_ttl, &client)?;
+            let provider =
+                MeasuredProvider::new(cdu.zone(), cdu.provider().clone(), metrics.clone());
+            Ok(cdu.with_provider(Arc::new(provider)))
+        })
+        .collect::<anyhow::Result<Vec<Cdu>>>()?;
 
-    let cdu = Cdu::new(&opts.token, &opts.zone, &record_names);
+    let runner = Runner {
+        notifier: opts.notifier(),
+        metrics: metrics.clone(),
+        output: opts.output,
+        retry: opts.retry_policy()?,
+        history: opts.history_file.as_ref().map(History::new),
+        hooks: opts.hooks(),
+    };
 
-    if opts.daemon {
+    if opts.dry_run {
+        if plan_all(&cdus, opts.output).await? {
+            std::process::exit(DRY_RUN_CHANGES_PENDING);
+        }
+    } else if opts.daemon {
         let cron = &opts.cron;
         debug!("run as daemon with cron {cron}");
-        run_daemon(&cdu, cron).await?;
+        if let Some(listen) = opts.listen {
+            let (addr, server) = metrics::serve(listen, metrics.clone(), opts.unhealthy_after)?;
+            info!("serve metrics and health on {addr}");
+            tokio::spawn(server);
+        }
+        let events = match &opts.watch_interface {
+            Some(interface) => {
+                info!("watch IP addresses added to {interface}");
+                Some(watch_interface(interface)?)
+            }
+            None => None,
+        };
+        runner.run_daemon(&cdus, cron, events).await?;
     } else {
-        let zone = &opts.zone;
-        let tmr = timer!(Level::Debug; "RUN_ONCE", "zone {zone}");
-        run_once(&cdu).await?;
-        finish!(tmr);
+        runner.run_all(&cdus, true).await?;
     }
 
     Ok(())
 }
 
-async fn run_once(cdu: &Cdu<'_>) -> anyhow::Result<()> {
-    let min = Duration::from_millis(100);
-    let max = Duration::from_secs(10);
-    let backoff = exponential_backoff::Backoff::new(10, min, max);
+/// Exit status of dry run when any DNS record would change
+const DRY_RUN_CHANGES_PENDING: i32 = 2;
 
-    let mut iter = backoff.iter();
-    loop {
-        let duration = iter.next();
-        match cdu.run().await {
-            Ok(_) => break,
-            Err(e) => {
-                if let Some(duration) = duration {
-                    if e.is::<ApiFailure>() || e.is::<NoIPV4>() {
-                        warn!("retry in {duration:?} because of {e}");
-                        thread::sleep(duration);
-                    } else {
-                        return Err(e);
-                    }
-                } else {
-                    return Err(e);
-                }
+/// Print planned changes of all [`Cdu`]s in text, or one JSON object per zone,
+/// returns whether any DNS record would change
+async fn plan_all(cdus: &[Cdu<'_>], output: OutputFormat) -> anyhow::Result<bool> {
+    let mut has_changes = false;
+    for cdu in cdus {
+        let plan = cdu
+            .plan()
+            .await
+            .with_context(|| format!("failed to plan zone {}", cdu.zone()))?;
+        match output {
+            OutputFormat::Text => println!("{plan}"),
+            OutputFormat::Json => println!("{}", serde_json::to_string(&plan)?),
+        }
+        has_changes |= plan.has_changes();
+    }
+    Ok(has_changes)
+}
+
+/// Print items one per line in text, or one JSON object per line
+fn print_lines<T: serde::Serialize>(
+    items: &[T],
+    output: OutputFormat,
+    text: impl Fn(&T) -> String,
+) -> anyhow::Result<()> {
+    for item in items {
+        match output {
+            OutputFormat::Text => println!("{}", text(item)),
+            OutputFormat::Json => println!("{}", serde_json::to_string(item)?),
+        }
+    }
+    Ok(())
+}
+
+fn token_status_text(status: &TokenStatus) -> String {
+    let mut text = format!("token {} is {}", status.id, status.status);
+    if let Some(not_before) = &status.not_before {
+        text.push_str(&format!(", valid from {not_before}"));
+    }
+    if let Some(expires_on) = &status.expires_on {
+        text.push_str(&format!(", expires on {expires_on}"));
+    }
+    match &status.permissions {
+        Some(permissions) if permissions.is_empty() => text.push_str("\n  no permissions"),
+        Some(permissions) => {
+            for permission in permissions {
+                text.push_str(&format!("\n  {permission}"));
             }
         }
+        None => text.push_str("\n  permissions unknown, token cannot read API tokens"),
     }
+    text
+}
 
+fn print_token_status(status: &TokenStatus, output: OutputFormat) -> anyhow::Result<()> {
+    print_lines(std::slice::from_ref(status), output, token_status_text)
+}
+
+fn zone_text(zone: &ZoneInfo) -> String {
+    format!("{} {} {}", zone.name, zone.id, zone.status)
+}
+
+fn print_zones(zones: &[ZoneInfo], output: OutputFormat) -> anyhow::Result<()> {
+    print_lines(zones, output, zone_text)
+}
+
+fn record_text(record: &RecordInfo) -> String {
+    let ttl = match record.ttl {
+        1 => "auto".to_string(),
+        ttl => ttl.to_string(),
+    };
+    let proxied = match record.proxied {
+        Some(true) => "proxied",
+        Some(false) | None => "dns only",
+    };
+    format!(
+        "{} {} {} ttl {ttl} {proxied}",
+        record.name, record.record_type, record.content
+    )
+}
+
+fn print_records(records: &[RecordInfo], output: OutputFormat) -> anyhow::Result<()> {
+    print_lines(records, output, record_text)
+}
+
+/// History entry with its number in JSON output
+#[derive(Debug, serde::Serialize)]
+struct Numbered<'a> {
+    number: usize,
+    #[serde(flatten)]
+    entry: &'a HistoryEntry,
+}
+
+/// Print changes in history file meeting conditions of filter, numbered for `rollback`
+fn print_history(
+    history: &History,
+    filter: &HistoryFilter,
+    limit: Option<usize>,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let entries = history.load()?;
+    let matched: Vec<(usize, &HistoryEntry)> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (i + 1, entry))
+        .filter(|(_, entry)| filter.matches(entry))
+        .collect();
+    let skip = limit.map_or(0, |limit| matched.len().saturating_sub(limit));
+    for (number, entry) in &matched[skip..] {
+        match output {
+            OutputFormat::Text => println!("{number:>4} {entry}"),
+            OutputFormat::Json => {
+                let numbered = Numbered {
+                    number: *number,
+                    entry,
+                };
+                println!("{}", serde_json::to_string(&numbered)?);
+            }
+        }
+    }
     Ok(())
 }
 
-async fn run_daemon<'a, T>(cdu: &Cdu<'_>, cron: T) -> anyhow::Result<()>
-where
-    T: Into<Cow<'a, str>>,
-{
-    let schedule = Schedule::from_str(cron.into().as_ref())?;
-    for datetime in schedule.upcoming(chrono::Utc) {
-        info!("update DNS records at {datetime}");
-
-        loop {
-            if chrono::Utc::now() > datetime {
-                break;
-            } else {
-                tokio::time::sleep(Duration::from_millis(999)).await;
+/// Zone settings to publish the IP address held before the change to the DNS records
+/// of the change, leaving IP lists of the zone alone
+fn rollback_zone(zones: Vec<ZoneConfig>, entry: &HistoryEntry) -> anyhow::Result<ZoneConfig> {
+    let previous = entry
+        .previous
+        .context("IP address held before the change is unknown")?;
+    let mut zone = zones
+        .into_iter()
+        .find(|z| z.zone == entry.zone)
+        .with_context(|| format!("zone {} is not configured", entry.zone))?;
+    let record_type = entry.record_type;
+    zone.records = entry
+        .records
+        .iter()
+        .map(|r| format!("{}:{record_type}", r.name))
+        .collect();
+    zone.ip_sources = vec![Arc::new(StaticIpSource::new(previous))];
+    zone.quorum = None;
+    zone.ip_lists.clear();
+    Ok(zone)
+}
+
+/// Run [`Cdu`], retry failures which may succeed later, including failed DNS records
+async fn run_once(cdu: &Cdu<'_>, policy: RetryPolicy) -> cdu::Result<RunReport> {
+    let backoff =
+        exponential_backoff::Backoff::new(policy.attempts, policy.min_delay, policy.max_delay);
+    let mut delays = backoff.iter().take(policy.attempts as usize);
+    let mut last: Option<RunReport> = None;
+    loop {
+        let res = match (cdu.run().await, last.take()) {
+            (Ok(report), Some(last)) => Ok(last.merge(report)),
+            (res, _) => res,
+        };
+        let retry = match &res {
+            Ok(report) => report.retry(),
+            Err(e) => e.retry(),
+        };
+        let (retry, backoff) = match (retry, delays.next()) {
+            (Some(retry), Some(backoff)) => (retry, backoff),
+            _ => return res,
+        };
+        let delay = match retry {
+            Retry::Backoff => backoff,
+            Retry::After(after) if after <= policy.max_delay => backoff.max(after),
+            Retry::After(after) => {
+                warn!(
+                    "stop retrying zone {} as DNS provider asks to retry after {after:?}",
+                    cdu.zone()
+                );
+                return res;
             }
+        };
+        match res {
+            Ok(report) => {
+                let failures = report.failures().len();
+                warn!(
+                    "retry {failures} failed DNS records of zone {} in {delay:?}",
+                    report.zone
+                );
+                last = Some(report);
+            }
+            Err(e) => warn!("retry in {delay:?} because of {e:#}"),
         }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_interface(interface: &str) -> anyhow::Result<mpsc::Receiver<IpAddr>> {
+    netlink::watch(interface)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_interface(_interface: &str) -> anyhow::Result<mpsc::Receiver<IpAddr>> {
+    anyhow::bail!("watching network interface is only supported on Linux")
+}
 
-        run_once(cdu).await?;
+/// Next IP address added to the watched network interface, pending forever if not watching
+async fn next_event(events: &mut Option<mpsc::Receiver<IpAddr>>) -> Option<IpAddr> {
+    match events {
+        Some(events) => events.recv().await,
+        None => futures::future::pending().await,
     }
+}
 
-    Ok(())
+/// Runs [`Cdu`]s, then reports outcomes
+#[derive(Debug)]
+struct Runner {
+    notifier: Option<Notifier>,
+    metrics: Arc<Metrics>,
+    output: OutputFormat,
+    retry: RetryPolicy,
+    history: Option<History>,
+    hooks: Option<Hooks>,
+}
+
+impl Runner {
+    fn print(&self, report: &RunReport) {
+        match self.output {
+            OutputFormat::Text => println!("{report}"),
+            OutputFormat::Json => match serde_json::to_string(report) {
+                Ok(json) => println!("{json}"),
+                Err(e) => error!("cannot serialize report of zone {}: {e}", report.zone),
+            },
+        }
+    }
+
+    /// Report run of zone, returns error if zone or any DNS record fails
+    async fn report(
+        &self,
+        zone: &str,
+        res: anyhow::Result<RunReport>,
+        print_unchanged: bool,
+    ) -> anyhow::Result<()> {
+        let report = match res {
+            Ok(report) => report,
+            Err(e) => {
+                error!("failed to update zone {zone}: {e:#}");
+                self.metrics.failure(zone);
+                if let Some(notifier) = &self.notifier {
+                    notifier.notify_failure(zone, &e).await;
+                }
+                return Err(e);
+            }
+        };
+        if print_unchanged || !report.is_empty() {
+            self.print(&report);
+        }
+        if let Some(notifier) = &self.notifier {
+            notifier.notify_changes(zone, &report.changes()).await;
+        }
+        if let Some(history) = &self.history {
+            let entries = HistoryEntry::from_report(&report, chrono::Utc::now());
+            if let Err(e) = history.append(&entries) {
+                error!("cannot record IP address changes of zone {zone}: {e:#}");
+            }
+        }
+        if let Some(hooks) = &self.hooks {
+            // hook failures do not fail the update, DNS records are published already
+            let events = HookEvent::of(zone, &report.changes());
+            for e in hooks.run(&events).await {
+                error!("hook failed after updating zone {zone}: {e:#}");
+                self.metrics.hook_failure(zone);
+                if let Some(notifier) = &self.notifier {
+                    notifier.notify_hook_failure(zone, &e).await;
+                }
+            }
+        }
+        if report.is_success() {
+            self.metrics.success(zone);
+            return Ok(());
+        }
+        self.metrics.failure(zone);
+        let (failures, list_failures) = (report.failures(), report.list_failures());
+        let mut counts = vec![];
+        if !failures.is_empty() {
+            let total = report.records.len();
+            counts.push(format!("{} of {total} DNS records failed", failures.len()));
+        }
+        if !list_failures.is_empty() {
+            let total = report.lists.len();
+            counts.push(format!(
+                "{} of {total} IP lists failed",
+                list_failures.len()
+            ));
+        }
+        let details: Vec<String> = failures
+            .iter()
+            .chain(&list_failures)
+            .map(|r| r.to_string())
+            .collect();
+        let e = anyhow::anyhow!("{}: {}", counts.join(", "), details.join("; "));
+        error!("failed to update zone {zone}: {e:#}");
+        if let Some(notifier) = &self.notifier {
+            notifier.notify_failure(zone, &e).await;
+        }
+        Err(e)
+    }
+
+    /// Run all [`Cdu`]s concurrently, returns the first error after all of them finish.
+    /// Reports of zones whose IP addresses remain unchanged are printed only if `print_unchanged`
+    async fn run_all(&self, cdus: &[Cdu<'_>], print_unchanged: bool) -> anyhow::Result<()> {
+        let tasks = cdus.iter().map(|cdu| async move {
+            let zone = cdu.zone();
+            let tmr = timer!(Level::Debug; "RUN_ONCE", "zone {zone}");
+            let res = run_once(cdu, self.retry).await.map_err(anyhow::Error::from);
+            finish!(tmr);
+            self.report(zone, res, print_unchanged).await
+        });
+        futures::future::join_all(tasks)
+            .await
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    async fn run_daemon<'a, T>(
+        &self,
+        cdus: &[Cdu<'_>],
+        cron: T,
+        mut events: Option<mpsc::Receiver<IpAddr>>,
+    ) -> anyhow::Result<()>
+    where
+        T: Into<Cow<'a, str>>,
+    {
+        let schedule = Schedule::from_str(cron.into().as_ref())?;
+        for datetime in schedule.upcoming(chrono::Utc) {
+            info!("update DNS records at {datetime}");
+
+            loop {
+                if chrono::Utc::now() > datetime {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(999)) => {}
+                    Some(ip) = next_event(&mut events) => {
+                        info!("update DNS records as {ip} is added");
+                        let _ = self.run_all(cdus, false).await;
+                    }
+                }
+            }
+
+            // failures are logged, notified and counted already, keep running on next schedule
+            let _ = self.run_all(cdus, false).await;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use cdu::Record;
+
     use super::*;
 
+    use cdu::fake::FakeProvider;
+
+    use crate::config::build_ip_source;
+
+    /// Command line options of zone x.com with extra arguments,
+    /// token and records a.x.com default unless other credentials or records are given
+    fn try_parse(extra: &[&str]) -> Result<Opts, clap::Error> {
+        let credentials = ["--token-file", "--email", "--api-key", "--rfc2136-server"];
+        let mut args = vec!["--", "-z", "x.com"];
+        if !extra.iter().any(|arg| credentials.contains(arg)) {
+            args.extend(["-t", "token"]);
+        }
+        if !extra.contains(&"-r") {
+            args.extend(["-r", "a.x.com"]);
+        }
+        args.extend(extra);
+        Opts::try_parse_from(args)
+    }
+
+    fn parse(extra: &[&str]) -> Opts {
+        try_parse(extra).unwrap()
+    }
+
+    /// DNS record holding IP address, with automatic TTL and not proxied
+    fn record(id: &str, name: &str, ip: &str) -> Record {
+        Record {
+            id: id.into(),
+            name: name.into(),
+            content: ip.into(),
+            ttl: 1,
+            proxied: Some(false),
+        }
+    }
+
+    /// Publish IP address to DNS records held in memory, as the zone of command line options
+    async fn run_zone(
+        opts: &Opts,
+        ip: &str,
+        provider: &Arc<FakeProvider>,
+    ) -> cdu::Result<RunReport> {
+        opts.zone_config()
+            .unwrap()
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap()
+            .with_provider(provider.clone())
+            .with_ip_source(Arc::new(StaticIpSource::new(ip.parse().unwrap())))
+            .run()
+            .await
+    }
+
     #[test]
     fn t_daemon_mode() {
         let opts = Opts::try_parse_from(vec![
@@ -135,8 +957,783 @@ mod tests {
         ])
         .unwrap();
         assert!(opts.daemon);
-        assert_eq!(opts.records, "records");
-        assert_eq!(opts.token, "token");
-        assert_eq!(opts.zone, "zone");
+        assert_eq!(opts.records.as_deref(), Some("records"));
+        assert_eq!(opts.token.as_deref(), Some("token"));
+        assert_eq!(opts.zone.as_deref(), Some("zone"));
+        assert_eq!(opts.record_types, vec![RecordType::A]);
+        assert_eq!(opts.state_file, None);
+        assert_eq!(opts.identifier_ttl, 86400);
+        assert_eq!(opts.ttl, None);
+        assert_eq!(opts.proxied, None);
+        assert_eq!(format!("{:?}", opts.ip_source), "[PublicIpSource]");
+        assert_eq!(opts.quorum, None);
+        assert!(opts.allowed_networks.is_empty());
+    }
+
+    #[test]
+    fn t_ip_source() {
+        let opts = parse(&["--ip-source", "interface=eth0"]);
+        assert_eq!(
+            format!("{:?}", opts.ip_source),
+            r#"[InterfaceIpSource { name: "eth0" }]"#
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                build_ip_source(&opts.ip_source, opts.quorum).unwrap()
+            ),
+            r#"InterfaceIpSource { name: "eth0" }"#
+        );
+
+        assert!(try_parse(&["--ip-source", "interface"]).is_err());
+    }
+
+    #[test]
+    fn t_quorum() {
+        let opts = parse(&[
+            "--ip-source",
+            "public",
+            "--ip-source",
+            "url=https://api.ipify.org",
+            "--ip-source",
+            "url=https://ifconfig.me/ip",
+        ]);
+        assert_eq!(3, opts.ip_source.len());
+        let source = format!(
+            "{:?}",
+            build_ip_source(&opts.ip_source, opts.quorum).unwrap()
+        );
+        assert!(source.starts_with("QuorumIpSource"));
+        assert!(source.ends_with("quorum: 2 }"));
+
+        let mut opts = opts;
+        opts.quorum = Some(4);
+        assert!(build_ip_source(&opts.ip_source, opts.quorum).is_err());
+    }
+
+    #[tokio::test]
+    async fn t_allowed_networks() {
+        let opts = parse(&["--allowed-networks", "10.0.0.0/8,fd00::/8"]);
+        assert_eq!(
+            opts.allowed_networks,
+            vec![
+                "10.0.0.0/8".parse::<IpNet>().unwrap(),
+                "fd00::/8".parse::<IpNet>().unwrap()
+            ]
+        );
+
+        let provider = Arc::new(FakeProvider::new(vec![record("1", "a.x.com", "1.1.1.1")]));
+        let e = run_zone(&parse(&[]), "10.1.2.3", &provider)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, cdu::Error::NonPublicIp(_)), "{e}");
+        assert_eq!(0, provider.writes());
+
+        let report = run_zone(&opts, "10.1.2.3", &provider).await.unwrap();
+        assert_eq!(cdu::RecordOutcome::Updated, report.records[0].outcome);
+        assert_eq!("10.1.2.3", provider.records()[0].content);
+    }
+
+    #[tokio::test]
+    async fn t_record_options() {
+        let opts = parse(&[
+            "-r",
+            "a.x.com,b.x.com,c.x.com",
+            "--ttl",
+            "300",
+            "--proxied",
+            "true",
+            "--record-ttl",
+            "a.x.com=60,b.x.com=1",
+            "--record-proxied",
+            "b.x.com=false",
+        ]);
+        assert_eq!(opts.ttl, Some(300));
+        assert_eq!(opts.proxied, Some(true));
+        assert_eq!(
+            opts.record_ttl,
+            vec![("a.x.com".to_string(), 60), ("b.x.com".to_string(), 1)]
+        );
+        assert_eq!(opts.record_proxied, vec![("b.x.com".to_string(), false)]);
+
+        // settings per record override the ones of the zone
+        let provider = Arc::new(FakeProvider::new(vec![
+            record("1", "a.x.com", "1.1.1.2"),
+            record("2", "b.x.com", "1.1.1.2"),
+            record("3", "c.x.com", "1.1.1.2"),
+        ]));
+        run_zone(&opts, "1.1.1.1", &provider).await.unwrap();
+        let settings: Vec<(u32, Option<bool>)> = provider
+            .records()
+            .iter()
+            .map(|r| (r.ttl, r.proxied))
+            .collect();
+        assert_eq!(
+            vec![(60, Some(true)), (1, Some(false)), (300, Some(true))],
+            settings
+        );
+
+        assert!(try_parse(&["--record-ttl", "a.x.com"]).is_err());
+    }
+
+    #[test]
+    fn t_record_types() {
+        let opts = parse(&["--record-types", "A,AAAA"]);
+        assert_eq!(opts.record_types, vec![RecordType::A, RecordType::AAAA]);
+    }
+
+    #[tokio::test]
+    async fn t_create_missing() {
+        let opts = parse(&[
+            "--create-missing",
+            "--create-ttl",
+            "300",
+            "--create-proxied",
+        ]);
+        assert!(opts.create_missing);
+        assert_eq!(opts.create_ttl, 300);
+        assert!(opts.create_proxied);
+
+        let provider = Arc::new(FakeProvider::default());
+        let report = run_zone(&opts, "1.1.1.1", &provider).await.unwrap();
+        assert_eq!(cdu::RecordOutcome::Created, report.records[0].outcome);
+        let record = &provider.records()[0];
+        assert_eq!(("a.x.com", "1.1.1.1"), (&*record.name, &*record.content));
+        assert_eq!((300, Some(true)), (record.ttl, record.proxied));
+
+        // missing DNS record fails without creation
+        let provider = Arc::new(FakeProvider::default());
+        let report = run_zone(&parse(&[]), "1.1.1.1", &provider).await.unwrap();
+        assert_eq!(cdu::RecordOutcome::Failed, report.records[0].outcome);
+        assert!(provider.records().is_empty());
+    }
+
+    #[test]
+    fn t_config() {
+        let opts = Opts::try_parse_from(vec!["--", "--config", "cdu.toml"]).unwrap();
+        assert_eq!(opts.config, Some(PathBuf::from("cdu.toml")));
+        assert!(opts.token.is_none());
+
+        assert!(Opts::try_parse_from(vec!["--", "-t", "token"]).is_err());
+    }
+
+    #[test]
+    fn t_api() {
+        let opts = parse(&[]);
+        assert_eq!(cdu::DEFAULT_API_URL, opts.api_url);
+        assert_eq!(30, opts.api_timeout);
+
+        let opts = parse(&[
+            "--api-url",
+            "http://127.0.0.1:8080/client/v4",
+            "--api-timeout",
+            "5",
+        ]);
+        assert_eq!("http://127.0.0.1:8080/client/v4", opts.api_url);
+        assert_eq!(5, opts.api_timeout);
+    }
+
+    #[test]
+    fn t_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        std::fs::write(&path, "token1\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let opts = parse(&["--token-file", path]);
+        assert_eq!(Credentials::from("token1"), opts.credentials().unwrap());
+        let zone = opts.zone_config().unwrap();
+        assert_eq!(
+            Some(Credentials::from("token1")),
+            zone.credentials().unwrap()
+        );
+
+        let opts = Opts::try_parse_from(vec![
+            "--",
+            "--email",
+            "a@x.com",
+            "--api-key-file",
+            path,
+            "zones",
+        ])
+        .unwrap();
+        assert_eq!(
+            Credentials::global_key("a@x.com", "token1"),
+            opts.credentials().unwrap()
+        );
+        let opts = Opts::try_parse_from(vec![
+            "--",
+            "--email",
+            "a@x.com",
+            "--api-key",
+            "key",
+            "zones",
+        ])
+        .unwrap();
+        assert_eq!(
+            Credentials::global_key("a@x.com", "key"),
+            opts.credentials().unwrap()
+        );
+
+        // email without global API key
+        let opts = Opts::try_parse_from(vec!["--", "--email", "a@x.com", "zones"]).unwrap();
+        assert!(opts.credentials().is_err());
+        // token conflicts with token file and global API key
+        assert!(
+            Opts::try_parse_from(vec!["--", "-t", "token", "--token-file", path, "zones"]).is_err()
+        );
+        assert!(
+            Opts::try_parse_from(vec!["--", "-t", "token", "--email", "a@x.com", "zones"]).is_err()
+        );
+        // global API key requires email
+        assert!(try_parse(&["--api-key", "key"]).is_err());
+    }
+
+    #[test]
+    fn t_rfc2136() {
+        let opts = parse(&[
+            "--rfc2136-server",
+            "127.0.0.1:53",
+            "--tsig-key-name",
+            "cdu-key",
+            "--tsig-secret",
+            "c2VjcmV0",
+        ]);
+        let zone = opts.zone_config().unwrap();
+        assert!(zone.token.is_none());
+        let rfc2136 = zone.rfc2136.as_ref().unwrap();
+        assert_eq!("cdu-key", rfc2136.key_name);
+        assert_eq!("hmac-sha256", rfc2136.algorithm);
+        assert!(zone
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .is_ok());
+
+        // TSIG key is required
+        assert!(try_parse(&["--rfc2136-server", "127.0.0.1:53"]).is_err());
+        // token conflicts with RFC 2136
+        assert!(try_parse(&[
+            "-t",
+            "token",
+            "--rfc2136-server",
+            "127.0.0.1:53",
+            "--tsig-key-name",
+            "cdu-key",
+            "--tsig-secret",
+            "c2VjcmV0",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn t_notifier() {
+        assert!(parse(&[]).notifier().is_none());
+
+        let opts = parse(&[
+            "--pushover-token",
+            "pushover-token",
+            "--pushover-user",
+            "pushover-user",
+            "--failure-priority",
+            "emergency",
+        ]);
+        let notifier = opts.notifier().unwrap();
+        assert_eq!("pushover-token", notifier.token);
+        assert_eq!("pushover-user", notifier.user);
+        assert_eq!(Priority::Normal, notifier.ip_change_priority);
+        assert_eq!(Priority::Emergency, notifier.failure_priority);
+
+        // user is required with token
+        assert!(try_parse(&["--pushover-token", "pushover-token"]).is_err());
+    }
+
+    #[test]
+    fn t_ip_lists() {
+        let opts = parse(&["--ip-lists", "account1/office,account2/home"]);
+        let zone = opts.zone_config().unwrap();
+        assert_eq!(
+            vec![
+                IpListConfig {
+                    account_id: "account1".into(),
+                    name: "office".into(),
+                    comment: Some("cdu".into()),
+                },
+                IpListConfig {
+                    account_id: "account2".into(),
+                    name: "home".into(),
+                    comment: Some("cdu".into()),
+                },
+            ],
+            zone.ip_lists
+        );
+        let cdu = zone
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap();
+        assert!(format!("{cdu:?}").contains("CloudflareIpList"));
+
+        for invalid in ["office", "/office", "account/"] {
+            assert!(try_parse(&["--ip-lists", invalid]).is_err());
+        }
+    }
+
+    #[test]
+    fn t_hooks() {
+        assert!(parse(&[]).hooks().is_none());
+
+        let opts = parse(&[
+            "--hook",
+            "wg set wg0 peer x endpoint $CDU_NEW_IP:51820",
+            "--hook",
+            "ufw reload",
+            "--hook-timeout",
+            "5",
+        ]);
+        let hooks = opts.hooks().unwrap();
+        assert_eq!(
+            vec!["wg set wg0 peer x endpoint $CDU_NEW_IP:51820", "ufw reload"],
+            hooks.commands
+        );
+        assert_eq!(Duration::from_secs(5), hooks.timeout);
+    }
+
+    #[test]
+    fn t_listen() {
+        let opts = parse(&[
+            "--daemon",
+            "--listen",
+            "127.0.0.1:9090",
+            "--unhealthy-after",
+            "5",
+        ]);
+        assert_eq!(Some("127.0.0.1:9090".parse().unwrap()), opts.listen);
+        assert_eq!(5, opts.unhealthy_after);
+
+        // listener is only in daemon mode
+        assert!(try_parse(&["--listen", "127.0.0.1:9090"]).is_err());
+    }
+
+    #[test]
+    fn t_watch_interface() {
+        let opts = parse(&["--daemon", "--watch-interface", "ppp0"]);
+        assert_eq!(Some("ppp0"), opts.watch_interface.as_deref());
+
+        // watching is only in daemon mode
+        assert!(try_parse(&["--watch-interface", "ppp0"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn t_next_event() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut events = Some(rx);
+        tx.send("1.1.1.1".parse().unwrap()).await.unwrap();
+        assert_eq!(
+            Some("1.1.1.1".parse().unwrap()),
+            next_event(&mut events).await
+        );
+
+        let mut events = None;
+        let res = tokio::time::timeout(Duration::from_millis(10), next_event(&mut events)).await;
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn t_output() {
+        assert_eq!(OutputFormat::Text, parse(&[]).output);
+        assert_eq!(OutputFormat::Json, parse(&["--output", "json"]).output);
+        assert!(try_parse(&["--output", "yaml"]).is_err());
+    }
+
+    #[test]
+    fn t_retry_policy() {
+        assert_eq!(
+            RetryPolicy {
+                attempts: 10,
+                min_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(10),
+            },
+            parse(&[]).retry_policy().unwrap()
+        );
+
+        let opts = parse(&[
+            "--retry-attempts",
+            "3",
+            "--retry-min-delay",
+            "500",
+            "--retry-max-delay",
+            "60000",
+        ]);
+        assert_eq!(
+            RetryPolicy {
+                attempts: 3,
+                min_delay: Duration::from_millis(500),
+                max_delay: Duration::from_secs(60),
+            },
+            opts.retry_policy().unwrap()
+        );
+
+        let opts = parse(&["--retry-min-delay", "2000", "--retry-max-delay", "1000"]);
+        assert!(opts.retry_policy().is_err());
+    }
+
+    #[tokio::test]
+    async fn t_run_once() {
+        let policy = RetryPolicy {
+            attempts: 3,
+            min_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(100),
+        };
+        let run = |retry_after, limited| async move {
+            let provider = Arc::new(
+                FakeProvider::new(vec![record("2", "a.x.com", "1.1.1.2")]).with_failures(
+                    (0..limited)
+                        .map(|_| cdu::Error::RateLimited {
+                            retry_after: Some(retry_after),
+                        })
+                        .collect(),
+                ),
+            );
+            let cdu = Cdu::from_provider(provider.clone(), "x.com", &["a.x.com"])
+                .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())));
+            let report = run_once(&cdu, policy).await.unwrap();
+            (report, provider.writes())
+        };
+
+        // succeed after waiting as asked
+        let (report, updates) = run(Duration::from_millis(10), 2).await;
+        assert!(report.is_success());
+        assert_eq!(cdu::RecordOutcome::Updated, report.records[0].outcome);
+        assert_eq!(3, updates);
+
+        // give up after all retries
+        let (report, updates) = run(Duration::from_millis(10), 10).await;
+        assert!(!report.is_success());
+        assert_eq!(4, updates);
+
+        // do not wait longer than maximum delay
+        let (report, updates) = run(Duration::from_secs(60), 1).await;
+        assert!(!report.is_success());
+        assert_eq!(1, updates);
+    }
+
+    #[tokio::test]
+    async fn t_report() {
+        let runner = Runner {
+            notifier: None,
+            metrics: Arc::new(Metrics::default()),
+            output: OutputFormat::Json,
+            retry: RetryPolicy {
+                attempts: 0,
+                min_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            },
+            history: None,
+            hooks: None,
+        };
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![],
+            lists: vec![],
+        };
+        assert!(runner.report("x.com", Ok(report), false).await.is_ok());
+        assert!(runner.metrics.unhealthy_zones(1).is_empty());
+
+        let failed = cdu::RecordReport {
+            name: "a.x.com".into(),
+            record_type: RecordType::A,
+            outcome: cdu::RecordOutcome::Failed,
+            previous: None,
+            ip: "1.1.1.1".parse().unwrap(),
+            error: Some("DNS record not found: a.x.com (A)".into()),
+            retry: None,
+        };
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![failed.clone()],
+            lists: vec![],
+        };
+        let e = runner.report("x.com", Ok(report), false).await.unwrap_err();
+        assert_eq!(
+            "1 of 1 DNS records failed: a.x.com (A): failed to publish 1.1.1.1: DNS record not found: a.x.com (A)",
+            e.to_string()
+        );
+
+        // IP lists fail on their own
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![],
+            lists: vec![cdu::RecordReport {
+                name: "list:office".into(),
+                error: Some("IP list not found: office".into()),
+                ..failed
+            }],
+        };
+        let e = runner.report("x.com", Ok(report), false).await.unwrap_err();
+        assert_eq!(
+            "1 of 1 IP lists failed: list:office (A): failed to publish 1.1.1.1: IP list not found: office",
+            e.to_string()
+        );
+        assert_eq!(vec!["x.com"], runner.metrics.unhealthy_zones(1));
+    }
+
+    #[tokio::test]
+    async fn t_report_hook_failure() {
+        let runner = Runner {
+            notifier: None,
+            metrics: Arc::new(Metrics::default()),
+            output: OutputFormat::Json,
+            retry: RetryPolicy {
+                attempts: 0,
+                min_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            },
+            history: None,
+            hooks: Some(Hooks {
+                commands: vec!["exit 1".into()],
+                timeout: Duration::from_secs(5),
+            }),
+        };
+        let updated = cdu::RecordReport {
+            name: "a.x.com".into(),
+            record_type: RecordType::A,
+            outcome: cdu::RecordOutcome::Updated,
+            previous: Some("1.1.1.1".parse().unwrap()),
+            ip: "1.1.1.2".parse().unwrap(),
+            error: None,
+            retry: None,
+        };
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![updated],
+            lists: vec![],
+        };
+        // update succeeds though hook fails
+        assert!(runner.report("x.com", Ok(report), false).await.is_ok());
+        assert!(runner.metrics.unhealthy_zones(1).is_empty());
+        assert!(runner
+            .metrics
+            .render()
+            .contains("cdu_hook_failures_total{zone=\"x.com\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn t_dry_run() {
+        let opts = parse(&["--dry-run"]);
+        assert!(opts.dry_run);
+        assert!(!opts.daemon);
+
+        assert!(try_parse(&["--dry-run", "--daemon"]).is_err());
+
+        // plan without updating DNS records
+        let provider = Arc::new(FakeProvider::new(vec![record("1", "a.x.com", "1.1.1.2")]));
+        let zone = opts.zone_config().unwrap();
+        let plan = zone
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap()
+            .with_provider(provider.clone())
+            .with_ip_source(Arc::new(StaticIpSource::new("1.1.1.1".parse().unwrap())))
+            .plan()
+            .await
+            .unwrap();
+        assert!(plan.has_changes());
+        assert_eq!(cdu::PlannedAction::Update, plan.changes[0].action);
+        assert_eq!(0, provider.writes());
+        assert_eq!("1.1.1.2", provider.records()[0].content);
+    }
+
+    #[test]
+    fn t_zone_config() {
+        let opts = parse(&[
+            "-r",
+            "a,b:AAAA",
+            "--record-ttl",
+            "a=60",
+            "--record-proxied",
+            "a=true",
+            "--create-missing",
+        ]);
+        let zone = opts.zone_config().unwrap();
+        assert_eq!(Some("token"), zone.token.as_deref());
+        assert_eq!("x.com", zone.zone);
+        assert_eq!(vec!["a", "b:AAAA"], zone.records);
+        assert_eq!(
+            RecordOptions {
+                ttl: Some(60),
+                proxied: Some(true)
+            },
+            zone.record_options["a"]
+        );
+        assert_eq!(1, zone.create_missing.unwrap().ttl);
+        assert!(zone
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn t_history() {
+        // zone settings are not required
+        let opts = Opts::try_parse_from(vec![
+            "--",
+            "--history-file",
+            "history.jsonl",
+            "history",
+            "--zone",
+            "x.com",
+            "--since",
+            "2024-01-02",
+            "--limit",
+            "10",
+        ])
+        .unwrap();
+        match &opts.command {
+            Some(Command::History { filter, limit }) => {
+                assert_eq!(Some("x.com"), filter.zone.as_deref());
+                assert!(filter.since.is_some());
+                assert_eq!(Some(10), *limit);
+            }
+            _ => panic!("expect history subcommand"),
+        }
+        assert!(opts.history().is_ok());
+
+        let opts = Opts::try_parse_from(vec!["--", "history"]).unwrap();
+        assert!(opts.history().is_err());
+        assert!(Opts::try_parse_from(vec!["--", "history", "--since", "yesterday"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn t_rollback() {
+        let opts = parse(&[
+            "-r",
+            "glob:*.x.com",
+            "--ip-source",
+            "public",
+            "--ip-source",
+            "interface=eth0",
+            "rollback",
+            "3",
+        ]);
+        assert!(matches!(
+            opts.command,
+            Some(Command::Rollback { number: 3 })
+        ));
+
+        let entry: HistoryEntry = serde_json::from_str(
+            r#"{"timestamp":"2024-01-02T03:04:05Z","zone":"x.com","type":"AAAA","previous":"2001:db8::1","ip":"2001:db8::2","records":[{"name":"a.x.com","outcome":"updated"},{"name":"b.x.com","outcome":"failed","error":"error"}]}"#,
+        )
+        .unwrap();
+        let zone = rollback_zone(vec![opts.zone_config().unwrap()], &entry).unwrap();
+        assert_eq!(vec!["a.x.com:AAAA", "b.x.com:AAAA"], zone.records);
+        assert_eq!(
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+            build_ip_source(&zone.ip_sources, zone.quorum)
+                .unwrap()
+                .fetch(RecordType::AAAA)
+                .await
+                .unwrap()
+        );
+
+        let e = rollback_zone(vec![], &entry).unwrap_err();
+        assert_eq!("zone x.com is not configured", e.to_string());
+        let entry = HistoryEntry {
+            previous: None,
+            ..entry
+        };
+        let e = rollback_zone(vec![opts.zone_config().unwrap()], &entry).unwrap_err();
+        assert_eq!(
+            "IP address held before the change is unknown",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn t_inspect() {
+        let opts = Opts::try_parse_from(vec!["--", "-t", "token", "verify"]).unwrap();
+        assert!(matches!(opts.command, Some(Command::Verify)));
+        assert!(opts.provider().is_ok());
+        let opts = Opts::try_parse_from(vec!["--", "zones"]).unwrap();
+        assert!(matches!(opts.command, Some(Command::Zones)));
+        let e = opts.provider().unwrap_err();
+        assert_eq!(
+            "token is required, set --token, --token-file or CLOUDFLARE_TOKEN",
+            e.to_string()
+        );
+        let opts = Opts::try_parse_from(vec!["--", "-t", "token", "records", "x.com"]).unwrap();
+        assert!(matches!(&opts.command, Some(Command::Records { zone }) if zone == "x.com"));
+        assert!(Opts::try_parse_from(vec!["--", "records"]).is_err());
+
+        let mut status = TokenStatus {
+            id: "t1".into(),
+            status: "active".into(),
+            not_before: None,
+            expires_on: Some("2030-01-01T00:00:00Z".into()),
+            permissions: Some(vec!["DNS Write".into(), "Zone Read".into()]),
+        };
+        assert_eq!(
+            "token t1 is active, expires on 2030-01-01T00:00:00Z\n  DNS Write\n  Zone Read",
+            token_status_text(&status)
+        );
+        status.permissions = None;
+        assert_eq!(
+            "token t1 is active, expires on 2030-01-01T00:00:00Z\n  permissions unknown, token cannot read API tokens",
+            token_status_text(&status)
+        );
+
+        let record = RecordInfo {
+            id: "r1".into(),
+            name: "x.com".into(),
+            record_type: "A".into(),
+            content: "1.1.1.1".into(),
+            proxied: Some(true),
+            ttl: 1,
+        };
+        assert_eq!("x.com A 1.1.1.1 ttl auto proxied", record_text(&record));
+        let record = RecordInfo {
+            record_type: "MX".into(),
+            content: "mail.x.com".into(),
+            proxied: None,
+            ttl: 3600,
+            ..record
+        };
+        assert_eq!(
+            "x.com MX mail.x.com ttl 3600 dns only",
+            record_text(&record)
+        );
+    }
+
+    #[tokio::test]
+    async fn t_rollback_ip_list() {
+        let opts = parse(&["--ip-lists", "account/office", "rollback", "1"]);
+        let updated = |name: &str| cdu::RecordReport {
+            name: name.into(),
+            record_type: RecordType::A,
+            outcome: cdu::RecordOutcome::Updated,
+            previous: "1.1.1.1".parse().ok(),
+            ip: "1.1.1.2".parse().unwrap(),
+            error: None,
+            retry: None,
+        };
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![updated("a.x.com")],
+            lists: vec![updated("list:office")],
+        };
+        // IP lists are left out of history
+        let entries = HistoryEntry::from_report(&report, chrono::Utc::now());
+        assert_eq!(1, entries.len());
+        let names: Vec<&str> = entries[0].records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["a.x.com"], names);
+
+        let zone = rollback_zone(vec![opts.zone_config().unwrap()], &entries[0]).unwrap();
+        assert_eq!(vec!["a.x.com:A"], zone.records);
+        assert!(zone.ip_lists.is_empty());
+
+        let provider = Arc::new(FakeProvider::new(vec![record("2", "a.x.com", "1.1.1.2")]));
+        let cdu = zone
+            .build(Duration::from_secs(60), &ApiClient::default())
+            .unwrap()
+            .with_provider(provider.clone());
+        let report = cdu.run().await.unwrap();
+        let outcomes: Vec<String> = report.records.iter().map(|r| r.to_string()).collect();
+        assert_eq!(vec!["a.x.com (A): updated 1.1.1.2 -> 1.1.1.1"], outcomes);
+        assert!(report.lists.is_empty());
+        assert_eq!("1.1.1.1", provider.records()[0].content);
     }
 }
@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cdu::{CreateOptions, DnsProvider, Record, RecordOptions, RecordType};
+use futures::future::BoxFuture;
+use warp::http::StatusCode;
+use warp::Filter;
+
+/// Requests and failures of one DNS provider operation
+#[derive(Clone, Copy, Debug, Default)]
+struct ApiStats {
+    count: u64,
+    errors: u64,
+    seconds: f64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    last_success: BTreeMap<String, f64>,
+    failures: BTreeMap<String, u64>,
+    consecutive_failures: BTreeMap<String, u64>,
+    hook_failures: BTreeMap<String, u64>,
+    current_ips: BTreeMap<(String, String, RecordType), IpAddr>,
+    updates: BTreeMap<(String, String, RecordType), u64>,
+    api: BTreeMap<&'static str, ApiStats>,
+}
+
+/// Metrics of runs in daemon mode, exposed in Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// Escape label value in Prometheus text format
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+impl Metrics {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a successful run of zone
+    pub fn success(&self, zone: &str) {
+        let mut inner = self.lock();
+        inner.last_success.insert(zone.to_string(), unix_time());
+        inner.consecutive_failures.insert(zone.to_string(), 0);
+        inner.failures.entry(zone.to_string()).or_default();
+    }
+
+    /// Record a failed run of zone after retries
+    pub fn failure(&self, zone: &str) {
+        let mut inner = self.lock();
+        *inner.failures.entry(zone.to_string()).or_default() += 1;
+        *inner
+            .consecutive_failures
+            .entry(zone.to_string())
+            .or_default() += 1;
+    }
+
+    /// Record a failed hook command after IP address change of zone
+    pub fn hook_failure(&self, zone: &str) {
+        *self
+            .lock()
+            .hook_failures
+            .entry(zone.to_string())
+            .or_default() += 1;
+    }
+
+    /// Record IP address held by DNS record of zone
+    fn current_ip(&self, zone: &str, name: &str, ip: IpAddr) {
+        let key = (zone.to_string(), name.to_string(), RecordType::of(&ip));
+        self.lock().current_ips.insert(key, ip);
+    }
+
+    /// Record DNS record of zone created or updated with IP address
+    fn update(&self, zone: &str, name: &str, ip: IpAddr) {
+        let key = (zone.to_string(), name.to_string(), RecordType::of(&ip));
+        let mut inner = self.lock();
+        *inner.updates.entry(key.clone()).or_default() += 1;
+        inner.current_ips.insert(key, ip);
+    }
+
+    /// Record latency and result of DNS provider operation
+    fn api(&self, operation: &'static str, elapsed: Duration, ok: bool) {
+        let mut inner = self.lock();
+        let stats = inner.api.entry(operation).or_default();
+        stats.count += 1;
+        stats.seconds += elapsed.as_secs_f64();
+        if !ok {
+            stats.errors += 1;
+        }
+    }
+
+    /// Zones failing at least the number of times in a row
+    pub fn unhealthy_zones(&self, max_failures: u64) -> Vec<String> {
+        self.lock()
+            .consecutive_failures
+            .iter()
+            .filter(|(_, n)| **n >= max_failures)
+            .map(|(zone, _)| zone.clone())
+            .collect()
+    }
+
+    /// Render metrics in Prometheus text format
+    pub fn render(&self) -> String {
+        let inner = self.lock();
+        let mut s = String::new();
+
+        s.push_str(
+            "# HELP cdu_last_success_timestamp_seconds Time of last successful run of zone\n",
+        );
+        s.push_str("# TYPE cdu_last_success_timestamp_seconds gauge\n");
+        for (zone, t) in &inner.last_success {
+            let zone = escape(zone);
+            let _ = writeln!(
+                s,
+                "cdu_last_success_timestamp_seconds{{zone=\"{zone}\"}} {t}"
+            );
+        }
+
+        s.push_str("# HELP cdu_failures_total Runs of zone failed after retries\n");
+        s.push_str("# TYPE cdu_failures_total counter\n");
+        for (zone, n) in &inner.failures {
+            let zone = escape(zone);
+            let _ = writeln!(s, "cdu_failures_total{{zone=\"{zone}\"}} {n}");
+        }
+
+        s.push_str("# HELP cdu_consecutive_failures Runs of zone failed in a row\n");
+        s.push_str("# TYPE cdu_consecutive_failures gauge\n");
+        for (zone, n) in &inner.consecutive_failures {
+            let zone = escape(zone);
+            let _ = writeln!(s, "cdu_consecutive_failures{{zone=\"{zone}\"}} {n}");
+        }
+
+        s.push_str(
+            "# HELP cdu_hook_failures_total Hook commands failed after IP address change of zone\n",
+        );
+        s.push_str("# TYPE cdu_hook_failures_total counter\n");
+        for (zone, n) in &inner.hook_failures {
+            let zone = escape(zone);
+            let _ = writeln!(s, "cdu_hook_failures_total{{zone=\"{zone}\"}} {n}");
+        }
+
+        s.push_str("# HELP cdu_record_ip_info IP address last seen in DNS record\n");
+        s.push_str("# TYPE cdu_record_ip_info gauge\n");
+        for ((zone, name, record_type), ip) in &inner.current_ips {
+            let (zone, name) = (escape(zone), escape(name));
+            let _ = writeln!(
+                s,
+                "cdu_record_ip_info{{zone=\"{zone}\",record=\"{name}\",type=\"{record_type}\",ip=\"{ip}\"}} 1"
+            );
+        }
+
+        s.push_str("# HELP cdu_record_updates_total DNS records created or updated\n");
+        s.push_str("# TYPE cdu_record_updates_total counter\n");
+        for ((zone, name, record_type), n) in &inner.updates {
+            let (zone, name) = (escape(zone), escape(name));
+            let _ = writeln!(
+                s,
+                "cdu_record_updates_total{{zone=\"{zone}\",record=\"{name}\",type=\"{record_type}\"}} {n}"
+            );
+        }
+
+        s.push_str("# HELP cdu_api_request_duration_seconds Latency of DNS provider requests\n");
+        s.push_str("# TYPE cdu_api_request_duration_seconds summary\n");
+        for (operation, stats) in &inner.api {
+            let _ = writeln!(
+                s,
+                "cdu_api_request_duration_seconds_sum{{operation=\"{operation}\"}} {}",
+                stats.seconds
+            );
+            let _ = writeln!(
+                s,
+                "cdu_api_request_duration_seconds_count{{operation=\"{operation}\"}} {}",
+                stats.count
+            );
+        }
+
+        s.push_str("# HELP cdu_api_errors_total Failed DNS provider requests\n");
+        s.push_str("# TYPE cdu_api_errors_total counter\n");
+        for (operation, stats) in &inner.api {
+            let _ = writeln!(
+                s,
+                "cdu_api_errors_total{{operation=\"{operation}\"}} {}",
+                stats.errors
+            );
+        }
+
+        s
+    }
+}
+
+/// [`DnsProvider`] measuring latency and results of requests to the inner provider
+#[derive(Debug)]
+pub struct MeasuredProvider {
+    zone: String,
+    provider: Arc<dyn DnsProvider>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeasuredProvider {
+    /// Measure requests to provider of zone by name
+    pub fn new<T: Into<String>>(
+        zone: T,
+        provider: Arc<dyn DnsProvider>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            zone: zone.into(),
+            provider,
+            metrics,
+        }
+    }
+}
+
+impl DnsProvider for MeasuredProvider {
+    fn zone_id<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, cdu::Result<String>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = self.provider.zone_id(zone).await;
+            self.metrics.api("zone_id", start.elapsed(), res.is_ok());
+            res
+        })
+    }
+
+    fn get_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, cdu::Result<Option<Record>>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = self.provider.get_record(zone_id, name, record_type).await;
+            self.metrics.api("get_record", start.elapsed(), res.is_ok());
+            if let Ok(Some(record)) = &res {
+                if let Ok(ip) = record.content.parse() {
+                    self.metrics.current_ip(&self.zone, name, ip);
+                }
+            }
+            res
+        })
+    }
+
+    fn list_records<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, cdu::Result<Vec<Record>>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = self.provider.list_records(zone_id, record_type).await;
+            self.metrics
+                .api("list_records", start.elapsed(), res.is_ok());
+            res
+        })
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: CreateOptions,
+    ) -> BoxFuture<'a, cdu::Result<String>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = self
+                .provider
+                .create_record(zone_id, name, ip, options)
+                .await;
+            self.metrics
+                .api("create_record", start.elapsed(), res.is_ok());
+            if res.is_ok() {
+                self.metrics.update(&self.zone, name, ip);
+            }
+            res
+        })
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: RecordOptions,
+    ) -> BoxFuture<'a, cdu::Result<()>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = self
+                .provider
+                .update_record(zone_id, record_id, name, ip, options)
+                .await;
+            self.metrics
+                .api("update_record", start.elapsed(), res.is_ok());
+            if res.is_ok() {
+                self.metrics.update(&self.zone, name, ip);
+            }
+            res
+        })
+    }
+}
+
+/// Routes of `/metrics` and `/healthz`, unhealthy if any zone fails the number of times in a row
+fn routes(
+    metrics: Arc<Metrics>,
+    unhealthy_after: u64,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let metrics_m = warp::any().map(move || metrics.clone());
+
+    let metrics_route = warp::path!("metrics")
+        .and(warp::get())
+        .and(metrics_m.clone())
+        .map(|metrics: Arc<Metrics>| {
+            warp::reply::with_header(
+                metrics.render(),
+                "content-type",
+                "text/plain; version=0.0.4",
+            )
+        });
+
+    let health_route =
+        warp::path!("healthz")
+            .and(warp::get())
+            .and(metrics_m)
+            .map(move |metrics: Arc<Metrics>| {
+                let zones = metrics.unhealthy_zones(unhealthy_after);
+                if zones.is_empty() {
+                    warp::reply::with_status("ok".to_string(), StatusCode::OK)
+                } else {
+                    warp::reply::with_status(
+                        format!("failing zones: {}", zones.join(",")),
+                        StatusCode::SERVICE_UNAVAILABLE,
+                    )
+                }
+            });
+
+    metrics_route.or(health_route)
+}
+
+/// Bind HTTP listener serving metrics and health, returns future to serve requests
+pub fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    unhealthy_after: u64,
+) -> anyhow::Result<(SocketAddr, impl std::future::Future<Output = ()>)> {
+    let log = warp::log("cdu::metrics");
+    let (addr, server) =
+        warp::serve(routes(metrics, unhealthy_after).with(log)).try_bind_ephemeral(addr)?;
+    Ok((addr, server))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_render() {
+        let metrics = Metrics::default();
+        metrics.success("x.com");
+        metrics.failure("y.com");
+        metrics.failure("y.com");
+        metrics.hook_failure("x.com");
+        metrics.current_ip("x.com", "a.x.com", "1.1.1.1".parse().unwrap());
+        metrics.update("x.com", "a.x.com", "1.1.1.2".parse().unwrap());
+        metrics.update("x.com", "a.x.com", "2606:4700::1".parse().unwrap());
+        // same name in another zone
+        metrics.update("y.com", "a.x.com", "1.1.1.3".parse().unwrap());
+        metrics.api("get_record", Duration::from_millis(250), true);
+        metrics.api("update_record", Duration::from_millis(500), false);
+
+        let s = metrics.render();
+        assert!(s.contains("cdu_last_success_timestamp_seconds{zone=\"x.com\"} "));
+        assert!(s.contains("cdu_failures_total{zone=\"x.com\"} 0\n"));
+        assert!(s.contains("cdu_failures_total{zone=\"y.com\"} 2\n"));
+        assert!(s.contains("cdu_consecutive_failures{zone=\"y.com\"} 2\n"));
+        assert!(s.contains("cdu_hook_failures_total{zone=\"x.com\"} 1\n"));
+        assert!(s.contains(
+            "cdu_record_ip_info{zone=\"x.com\",record=\"a.x.com\",type=\"A\",ip=\"1.1.1.2\"} 1\n"
+        ));
+        assert!(s.contains(
+            "cdu_record_ip_info{zone=\"x.com\",record=\"a.x.com\",type=\"AAAA\",ip=\"2606:4700::1\"} 1\n"
+        ));
+        assert!(s.contains(
+            "cdu_record_ip_info{zone=\"y.com\",record=\"a.x.com\",type=\"A\",ip=\"1.1.1.3\"} 1\n"
+        ));
+        assert!(s.contains(
+            "cdu_record_updates_total{zone=\"x.com\",record=\"a.x.com\",type=\"A\"} 1\n"
+        ));
+        assert!(s.contains("cdu_api_request_duration_seconds_sum{operation=\"get_record\"} 0.25\n"));
+        assert!(
+            s.contains("cdu_api_request_duration_seconds_count{operation=\"update_record\"} 1\n")
+        );
+        assert!(s.contains("cdu_api_errors_total{operation=\"get_record\"} 0\n"));
+        assert!(s.contains("cdu_api_errors_total{operation=\"update_record\"} 1\n"));
+    }
+
+    #[test]
+    fn t_escape() {
+        assert_eq!(r#"a\"b\\c\n"#, escape("a\"b\\c\n"));
+    }
+
+    #[test]
+    fn t_unhealthy_zones() {
+        let metrics = Metrics::default();
+        assert!(metrics.unhealthy_zones(1).is_empty());
+
+        metrics.failure("x.com");
+        metrics.failure("x.com");
+        assert!(metrics.unhealthy_zones(3).is_empty());
+        metrics.failure("x.com");
+        assert_eq!(vec!["x.com"], metrics.unhealthy_zones(3));
+
+        metrics.success("x.com");
+        assert!(metrics.unhealthy_zones(3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn t_routes() {
+        let metrics = Arc::new(Metrics::default());
+        let routes = routes(metrics.clone(), 2);
+
+        let res = warp::test::request().path("/metrics").reply(&routes).await;
+        assert_eq!(StatusCode::OK, res.status());
+        assert!(String::from_utf8_lossy(res.body()).contains("# TYPE cdu_failures_total counter"));
+
+        let res = warp::test::request().path("/healthz").reply(&routes).await;
+        assert_eq!(StatusCode::OK, res.status());
+
+        metrics.failure("x.com");
+        metrics.failure("x.com");
+        let res = warp::test::request().path("/healthz").reply(&routes).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        assert_eq!("failing zones: x.com", String::from_utf8_lossy(res.body()));
+    }
+}
@@ -0,0 +1,156 @@
+use std::net::IpAddr;
+
+use anyhow::Context as _;
+use futures::{StreamExt, TryStreamExt};
+use log::{debug, error, warn};
+use netlink_packet_core::{NetlinkMessage, NetlinkPayload};
+use netlink_packet_route::address::nlas::Nla;
+use netlink_packet_route::link::nlas::Nla as LinkNla;
+use netlink_packet_route::{
+    RtnlMessage, RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV6_IFADDR, RT_SCOPE_UNIVERSE,
+};
+use netlink_sys::{AsyncSocket, SocketAddr};
+use rtnetlink::Handle;
+use tokio::sync::mpsc;
+
+/// Multicast groups of IPv4 and IPv6 address changes
+const ADDRESS_GROUPS: u32 = (1 << (RTNLGRP_IPV4_IFADDR - 1)) | (1 << (RTNLGRP_IPV6_IFADDR - 1));
+
+fn ip_of(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+/// Index of network interface and global IP address added to it, if message is about one
+fn new_address(message: NetlinkMessage<RtnlMessage>) -> Option<(u32, IpAddr)> {
+    let message = match message.payload {
+        NetlinkPayload::InnerMessage(RtnlMessage::NewAddress(message)) => message,
+        _ => return None,
+    };
+    if message.header.scope != RT_SCOPE_UNIVERSE {
+        return None;
+    }
+    // on point-to-point interfaces e.g. PPPoE, local address is in IFA_LOCAL and peer in IFA_ADDRESS
+    let local = message.nlas.iter().find_map(|nla| match nla {
+        Nla::Local(bytes) => ip_of(bytes),
+        _ => None,
+    });
+    let address = message.nlas.iter().find_map(|nla| match nla {
+        Nla::Address(bytes) => ip_of(bytes),
+        _ => None,
+    });
+    local.or(address).map(|ip| (message.header.index, ip))
+}
+
+async fn interface_name(handle: &Handle, index: u32) -> anyhow::Result<Option<String>> {
+    let mut links = handle.link().get().match_index(index).execute();
+    while let Some(link) = links.try_next().await? {
+        let name = link.nlas.into_iter().find_map(|nla| match nla {
+            LinkNla::IfName(name) => Some(name),
+            _ => None,
+        });
+        if name.is_some() {
+            return Ok(name);
+        }
+    }
+    Ok(None)
+}
+
+/// Subscribe to rtnetlink address changes, receive global IP addresses added to network interface.
+/// Addresses added while the last one is not received yet are dropped
+pub fn watch(interface: &str) -> anyhow::Result<mpsc::Receiver<IpAddr>> {
+    let (mut conn, handle, mut messages) =
+        rtnetlink::new_connection().context("cannot open netlink socket")?;
+    conn.socket_mut()
+        .socket_mut()
+        .bind(&SocketAddr::new(0, ADDRESS_GROUPS))
+        .context("cannot subscribe to address changes")?;
+    tokio::spawn(conn);
+
+    let (tx, rx) = mpsc::channel(1);
+    let interface = interface.to_string();
+    tokio::spawn(async move {
+        while let Some((message, _)) = messages.next().await {
+            let (index, ip) = match new_address(message) {
+                Some(added) => added,
+                None => continue,
+            };
+            match interface_name(&handle, index).await {
+                Ok(Some(name)) if name == interface => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("cannot find network interface {index}: {e}");
+                    continue;
+                }
+            }
+            debug!("{ip} added to {interface}");
+            if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(ip) {
+                return;
+            }
+        }
+        error!("netlink socket closed, stop watching {interface}");
+    });
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use netlink_packet_route::{AddressMessage, LinkMessage, AF_INET, AF_INET6, RT_SCOPE_LINK};
+
+    use super::*;
+
+    fn address_message(family: u16, scope: u8, nlas: Vec<Nla>) -> NetlinkMessage<RtnlMessage> {
+        let mut message = AddressMessage::default();
+        message.header.family = family as u8;
+        message.header.scope = scope;
+        message.header.index = 3;
+        message.nlas = nlas;
+        NetlinkMessage::from(RtnlMessage::NewAddress(message))
+    }
+
+    #[test]
+    fn t_ip_of() {
+        assert_eq!(Some("1.1.1.1".parse().unwrap()), ip_of(&[1, 1, 1, 1]));
+        assert_eq!(
+            Some("2606:4700::1".parse().unwrap()),
+            ip_of(&[0x26, 0x06, 0x47, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1])
+        );
+        assert_eq!(None, ip_of(&[1, 1, 1]));
+    }
+
+    #[test]
+    fn t_new_address() {
+        let message = address_message(
+            AF_INET,
+            RT_SCOPE_UNIVERSE,
+            vec![Nla::Address(vec![1, 1, 1, 1])],
+        );
+        assert_eq!(Some((3, "1.1.1.1".parse().unwrap())), new_address(message));
+
+        // local address of point-to-point interface
+        let message = address_message(
+            AF_INET,
+            RT_SCOPE_UNIVERSE,
+            vec![
+                Nla::Address(vec![10, 0, 0, 1]),
+                Nla::Local(vec![1, 1, 1, 1]),
+            ],
+        );
+        assert_eq!(Some((3, "1.1.1.1".parse().unwrap())), new_address(message));
+
+        // link-local address
+        let mut fe80 = vec![0; 16];
+        fe80[0] = 0xfe;
+        fe80[1] = 0x80;
+        fe80[15] = 1;
+        let message = address_message(AF_INET6, RT_SCOPE_LINK, vec![Nla::Address(fe80)]);
+        assert_eq!(None, new_address(message));
+
+        // not an address message
+        let message = NetlinkMessage::from(RtnlMessage::NewLink(LinkMessage::default()));
+        assert_eq!(None, new_address(message));
+    }
+}
@@ -0,0 +1,115 @@
+use cdu::RecordChange;
+use log::{debug, warn};
+use pushover::{Notification, Priority};
+
+/// Notification through Pushover on IP address changes and update failures
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    /// Pushover token
+    pub token: String,
+    /// Pushover user or group
+    pub user: String,
+    /// Priority of notifications on IP address changes
+    pub ip_change_priority: Priority,
+    /// Priority of notifications on update failures
+    pub failure_priority: Priority,
+}
+
+/// Message on DNS records which change IP address, if any
+fn changes_message(zone: &str, changes: &[RecordChange]) -> Option<String> {
+    if changes.is_empty() {
+        return None;
+    }
+    let mut message = format!("IP address changed in zone {zone}");
+    for change in changes {
+        message.push('\n');
+        message.push_str(&change.to_string());
+    }
+    Some(message)
+}
+
+fn failure_message(zone: &str, e: &anyhow::Error) -> String {
+    format!("failed to update zone {zone}: {e:#}")
+}
+
+fn hook_failure_message(zone: &str, e: &anyhow::Error) -> String {
+    format!("DNS records of zone {zone} are updated, but hook failed: {e:#}")
+}
+
+impl Notifier {
+    /// Notify DNS records which change IP address, does nothing if there is none
+    pub async fn notify_changes(&self, zone: &str, changes: &[RecordChange]) {
+        if let Some(message) = changes_message(zone, changes) {
+            self.send(&message, self.ip_change_priority).await;
+        }
+    }
+
+    /// Notify failure to update zone after retries
+    pub async fn notify_failure(&self, zone: &str, e: &anyhow::Error) {
+        self.send(&failure_message(zone, e), self.failure_priority)
+            .await;
+    }
+
+    /// Notify failure of hook command after IP address change of zone
+    pub async fn notify_hook_failure(&self, zone: &str, e: &anyhow::Error) {
+        self.send(&hook_failure_message(zone, e), self.failure_priority)
+            .await;
+    }
+
+    /// Send notification, failure is logged only so it does not fail the update
+    async fn send(&self, message: &str, priority: Priority) {
+        let mut notification = Notification::new(self.token.as_str(), self.user.as_str(), message);
+        notification.title = Some("cdu");
+        notification.priority = Some(priority);
+        debug!("send pushover notification {message:?}");
+        match notification.send().await {
+            Ok(res) => debug!("pushover response {res:?}"),
+            Err(e) => warn!("failed to send pushover notification: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_changes_message() {
+        assert!(changes_message("x.com", &[]).is_none());
+
+        let changes = vec![
+            RecordChange {
+                name: "a.x.com".into(),
+                record_type: cdu::RecordType::A,
+                previous: Some("1.1.1.1".parse().unwrap()),
+                current: "1.1.1.2".parse().unwrap(),
+            },
+            RecordChange {
+                name: "b.x.com".into(),
+                record_type: cdu::RecordType::AAAA,
+                previous: None,
+                current: "2606:4700::1".parse().unwrap(),
+            },
+        ];
+        assert_eq!(
+            "IP address changed in zone x.com\n\
+            a.x.com (A): 1.1.1.1 -> 1.1.1.2\n\
+            b.x.com (AAAA): (unknown) -> 2606:4700::1",
+            changes_message("x.com", &changes).unwrap()
+        );
+    }
+
+    #[test]
+    fn t_failure_message() {
+        let e = anyhow::anyhow!("zone not found: x.com");
+        assert_eq!(
+            "failed to update zone x.com: zone not found: x.com",
+            failure_message("x.com", &e)
+        );
+        let e = anyhow::anyhow!("hook exited with exit status: 1: wg-quick up wg0");
+        assert_eq!(
+            "DNS records of zone x.com are updated, but hook failed: hook exited with exit status: 1: wg-quick up wg0",
+            hook_failure_message("x.com", &e)
+        );
+    }
+}
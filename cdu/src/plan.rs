@@ -0,0 +1,215 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+use serde::ser::SerializeStruct as _;
+use serde::{Serialize, Serializer};
+
+use crate::RecordType;
+
+/// Action planned on one DNS record
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// DNS record does not exist and will be created
+    Create,
+    /// DNS record will be updated
+    Update,
+    /// DNS record holds the IP address and settings already
+    Unchanged,
+    /// DNS record does not exist and will not be created, so update will fail
+    Missing,
+}
+
+/// Serialize current and new setting as `{"current":...,"new":...}`
+fn serialize_setting<T, S>(
+    setting: &Option<(Option<T>, T)>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    match setting {
+        Some((current, new)) => {
+            let mut s = serializer.serialize_struct("Setting", 2)?;
+            s.serialize_field("current", current)?;
+            s.serialize_field("new", new)?;
+            s.end()
+        }
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Change planned on one DNS record
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedChange {
+    /// DNS record name
+    pub name: String,
+    /// DNS record type
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    /// Action planned
+    pub action: PlannedAction,
+    /// Current content of DNS record, if it exists
+    pub current_content: Option<String>,
+    /// IP address to publish
+    pub new_content: IpAddr,
+    /// Current and new time to live, if time to live changes
+    #[serde(
+        serialize_with = "serialize_setting",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttl: Option<(Option<u32>, u32)>,
+    /// Current and new proxied setting, if proxied setting changes
+    #[serde(
+        serialize_with = "serialize_setting",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub proxied: Option<(Option<bool>, bool)>,
+}
+
+impl Display for PlannedChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, record_type, new) = (&self.name, self.record_type, self.new_content);
+        let current = self.current_content.as_deref().unwrap_or("(none)");
+        match self.action {
+            PlannedAction::Create => write!(f, "{name} ({record_type}): create {new}")?,
+            PlannedAction::Update => {
+                write!(f, "{name} ({record_type}): update {current} -> {new}")?
+            }
+            PlannedAction::Unchanged => {
+                return write!(f, "{name} ({record_type}): unchanged {current}")
+            }
+            PlannedAction::Missing => {
+                return write!(f, "{name} ({record_type}): missing, cannot update to {new}")
+            }
+        }
+        if let Some((current, new)) = self.ttl {
+            match current {
+                Some(current) => write!(f, ", ttl {current} -> {new}")?,
+                None => write!(f, ", ttl {new}")?,
+            }
+        }
+        if let Some((current, new)) = self.proxied {
+            match current {
+                Some(current) => write!(f, ", proxied {current} -> {new}")?,
+                None => write!(f, ", proxied {new}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Changes planned on DNS records of a zone
+#[derive(Clone, Debug, Serialize)]
+pub struct Plan {
+    /// Zone name
+    pub zone: String,
+    /// Changes planned on DNS records
+    pub changes: Vec<PlannedChange>,
+}
+
+impl Plan {
+    /// Whether any DNS record will be created or updated
+    pub fn has_changes(&self) -> bool {
+        self.changes.iter().any(|c| {
+            matches!(
+                c.action,
+                PlannedAction::Create | PlannedAction::Update | PlannedAction::Missing
+            )
+        })
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "zone {}", self.zone)?;
+        for change in &self.changes {
+            write!(f, "\n  {change}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_display_plan() {
+        let change = PlannedChange {
+            name: "a.x.com".into(),
+            record_type: RecordType::A,
+            action: PlannedAction::Unchanged,
+            current_content: Some("1.1.1.1".into()),
+            new_content: "1.1.1.1".parse().unwrap(),
+            ttl: None,
+            proxied: None,
+        };
+        let mut plan = Plan {
+            zone: "x.com".into(),
+            changes: vec![change.clone()],
+        };
+        assert!(!plan.has_changes());
+
+        plan.changes.push(PlannedChange {
+            name: "b.x.com".into(),
+            action: PlannedAction::Update,
+            current_content: Some("1.1.1.2".into()),
+            ttl: Some((Some(300), 60)),
+            ..change.clone()
+        });
+        plan.changes.push(PlannedChange {
+            name: "c.x.com".into(),
+            record_type: RecordType::AAAA,
+            action: PlannedAction::Create,
+            current_content: None,
+            new_content: "::1".parse().unwrap(),
+            ttl: Some((None, 1)),
+            proxied: Some((None, true)),
+        });
+        plan.changes.push(PlannedChange {
+            name: "d.x.com".into(),
+            action: PlannedAction::Missing,
+            current_content: None,
+            ..change
+        });
+        assert!(plan.has_changes());
+        assert_eq!(
+            "zone x.com\n  \
+            a.x.com (A): unchanged 1.1.1.1\n  \
+            b.x.com (A): update 1.1.1.2 -> 1.1.1.1, ttl 300 -> 60\n  \
+            c.x.com (AAAA): create ::1, ttl 1, proxied true\n  \
+            d.x.com (A): missing, cannot update to 1.1.1.1",
+            plan.to_string()
+        );
+
+        plan.changes.drain(..1);
+        plan.changes.truncate(2);
+        assert_eq!(
+            serde_json::json!({
+                "zone": "x.com",
+                "changes": [
+                    {
+                        "name": "b.x.com",
+                        "type": "A",
+                        "action": "update",
+                        "current_content": "1.1.1.2",
+                        "new_content": "1.1.1.1",
+                        "ttl": {"current": 300, "new": 60},
+                    },
+                    {
+                        "name": "c.x.com",
+                        "type": "AAAA",
+                        "action": "create",
+                        "current_content": null,
+                        "new_content": "::1",
+                        "ttl": {"current": null, "new": 1},
+                        "proxied": {"current": null, "new": true},
+                    },
+                ],
+            }),
+            serde_json::to_value(&plan).unwrap()
+        );
+    }
+}
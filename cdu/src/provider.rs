@@ -0,0 +1,116 @@
+use std::fmt::Debug;
+use std::net::IpAddr;
+
+use futures::future::BoxFuture;
+
+use crate::{CreateOptions, Error, RecordOptions, RecordType, Result};
+
+pub use self::cloudflare::{
+    ApiClient, CloudflareIpList, CloudflareProvider, Credentials, RecordInfo, TokenStatus,
+    ZoneInfo, DEFAULT_API_TIMEOUT, DEFAULT_API_URL, DEFAULT_LIST_ITEM_COMMENT,
+};
+pub use self::rfc2136::{Rfc2136Provider, DEFAULT_RFC2136_TTL};
+
+mod cloudflare;
+mod rfc2136;
+
+/// DNS record held by a [`DnsProvider`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record {
+    /// Identifier of DNS record
+    pub id: String,
+    /// DNS record name
+    pub name: String,
+    /// Content of DNS record e.g. IP address
+    pub content: String,
+    /// Time to live
+    pub ttl: u32,
+    /// Whether the record is proxied, if the provider supports proxying
+    pub proxied: Option<bool>,
+}
+
+/// DNS service which holds DNS records of zones.
+/// Zone or DNS record which does not exist anymore fails with [`Error::ZoneNotFound`] or
+/// [`Error::RecordNotFound`], so cached identifiers are invalidated
+pub trait DnsProvider: Debug + Send + Sync {
+    /// Find identifier of zone by name
+    fn zone_id<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    /// Find DNS record by name and record type
+    fn get_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Option<Record>>>;
+
+    /// List all DNS records of the record type in zone, to expand record selectors
+    fn list_records<'a>(
+        &'a self,
+        _zone_id: &'a str,
+        _record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<Record>>> {
+        Box::pin(async {
+            Err(Error::Api {
+                status: None,
+                message: "listing DNS records is not supported by the provider".into(),
+                retry_after: None,
+            })
+        })
+    }
+
+    /// Create DNS record holding IP address, returns identifier of DNS record
+    fn create_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: CreateOptions,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Update DNS record to hold IP address, with settings set in options
+    fn update_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: RecordOptions,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Item of an [`IpList`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ListItem {
+    /// Identifier of item
+    pub id: String,
+    /// IP address or CIDR range e.g. `2001:db8::/64`
+    pub ip: String,
+    /// Comment of item, if any
+    pub comment: Option<String>,
+}
+
+/// List of IP addresses e.g. referenced by firewall rules, kept holding the published
+/// IP addresses. Items added by cdu carry [`IpList::comment`], so they are replaced
+/// on change rather than left behind
+pub trait IpList: Debug + Send + Sync {
+    /// Name of list
+    fn name(&self) -> &str;
+
+    /// Comment of items added by cdu
+    fn comment(&self) -> &str;
+
+    /// Item holding IP address, e.g. IPv6 address may be held as its /64 range
+    fn item_of(&self, ip: IpAddr) -> String {
+        ip.to_string()
+    }
+
+    /// List all items
+    fn items(&self) -> BoxFuture<'_, Result<Vec<ListItem>>>;
+
+    /// Add item holding IP address with comment
+    fn add_item(&self, ip: IpAddr) -> BoxFuture<'_, Result<()>>;
+
+    /// Remove items by identifier
+    fn remove_items<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>>;
+}
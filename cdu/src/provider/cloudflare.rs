@@ -0,0 +1,1367 @@
+use std::fmt::{Formatter, Result as FmtResult};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use cloudflare::endpoints::dns::{DnsContent, DnsRecord};
+use cloudflare::endpoints::zone::Zone;
+use cloudflare::framework::response::{ApiErrors, ApiSuccess};
+use futures::future::BoxFuture;
+use futures::FutureExt as _;
+use log::{debug, Level};
+use logging_timer::{finish, stimer};
+use reqwest::header::{ACCEPT, RETRY_AFTER};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::provider::{DnsProvider, IpList, ListItem, Record};
+use crate::{CreateOptions, Error, IpNet, RecordOptions, RecordType, Result};
+
+/// Base URL of Cloudflare API
+pub const DEFAULT_API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+/// Timeout of each request to Cloudflare API
+pub const DEFAULT_API_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of items per page when listing zones, DNS records and IP lists
+const PER_PAGE: u32 = 50;
+
+/// Comment of IP list items added by cdu
+pub const DEFAULT_LIST_ITEM_COMMENT: &str = "cdu";
+
+/// Delay between polls of bulk operation on IP list items
+const BULK_OPERATION_POLL_DELAY: Duration = Duration::from_millis(500);
+
+/// Number of polls of bulk operation on IP list items before giving up
+const BULK_OPERATION_POLLS: u32 = 60;
+
+/// Pagination of list endpoints in `result_info`
+#[derive(Debug, Deserialize)]
+struct ResultInfo {
+    page: u32,
+    total_pages: u32,
+}
+
+/// Delay of `Retry-After` header in seconds or HTTP date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // a date in the past means retry right away
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Messages of errors in body of failed response, or the body as it is
+fn error_message(body: &str) -> String {
+    match serde_json::from_str::<ApiErrors>(body) {
+        Ok(res) if !res.errors.is_empty() => res
+            .errors
+            .iter()
+            .map(|e| format!("{} {}", e.code, e.message))
+            .collect::<Vec<String>>()
+            .join(", "),
+        _ => body.trim().to_string(),
+    }
+}
+
+/// Classify failed response by HTTP status e.g. rate limits, server errors which may succeed
+/// on retry, and refused credentials
+async fn check_status(res: Response) -> Result<Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let retry_after = res
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, Utc::now()));
+    let body = res.text().await.unwrap_or_default();
+    let message = format!("HTTP {status}: {}", error_message(&body));
+    Err(match status {
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited { retry_after },
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Auth { message },
+        _ => Error::Api {
+            status: Some(status.as_u16()),
+            message,
+            retry_after,
+        },
+    })
+}
+
+/// Failure to send request or read response
+fn request_error(e: reqwest::Error) -> Error {
+    if e.is_decode() {
+        return Error::Api {
+            status: e.status().map(|s| s.as_u16()),
+            message: format!("invalid response: {e}"),
+            retry_after: None,
+        };
+    }
+    Error::Transport {
+        source: Box::new(e),
+    }
+}
+
+/// Send request authenticated with credentials, then decode response
+async fn send<T: DeserializeOwned>(req: RequestBuilder, credentials: &Credentials) -> Result<T> {
+    let res = credentials
+        .authenticate(req)
+        .send()
+        .await
+        .map_err(request_error)?;
+    check_status(res).await?.json().await.map_err(request_error)
+}
+
+/// Fetch all pages of list endpoint
+async fn list<T: DeserializeOwned>(
+    client: &ApiClient,
+    credentials: &Credentials,
+    path: &str,
+    query: &[(&str, &str)],
+) -> Result<Vec<T>> {
+    let mut items = vec![];
+    let mut page = 1;
+    loop {
+        let req = client
+            .request(Method::GET, path)
+            .query(&[("page", page), ("per_page", PER_PAGE)])
+            .query(query);
+        let res: ApiSuccess<Vec<T>> = send(req, credentials).await?;
+        items.extend(res.result);
+        let info: Option<ResultInfo> = res
+            .result_info
+            .and_then(|info| serde_json::from_value(info).ok());
+        match info {
+            Some(info) if info.page < info.total_pages => page = info.page + 1,
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
+/// Replace HTTP 404 with the error of what does not exist
+fn not_found(e: Error, f: impl FnOnce() -> Error) -> Error {
+    match e {
+        Error::Api {
+            status: Some(404), ..
+        } => f(),
+        e => e,
+    }
+}
+
+fn zone_not_found(zone_id: &str) -> Error {
+    Error::ZoneNotFound {
+        zone: zone_id.to_string(),
+    }
+}
+
+fn content_of(content: &DnsContent) -> String {
+    match content {
+        DnsContent::A { content } => content.to_string(),
+        DnsContent::AAAA { content } => content.to_string(),
+        _ => "(not an A or AAAA record)".into(),
+    }
+}
+
+impl From<DnsRecord> for Record {
+    fn from(r: DnsRecord) -> Self {
+        Self {
+            content: content_of(&r.content),
+            id: r.id,
+            name: r.name,
+            ttl: r.ttl,
+            proxied: Some(r.proxied),
+        }
+    }
+}
+
+/// HTTP client of Cloudflare API. Clones share connections, so providers of multiple zones
+/// built from one [`ApiClient`] reuse connections
+#[derive(Clone, Debug)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl Default for ApiClient {
+    fn default() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_API_URL.to_string(),
+            timeout: DEFAULT_API_TIMEOUT,
+        }
+    }
+}
+
+impl ApiClient {
+    /// Set base URL of Cloudflare API, defaults to [`DEFAULT_API_URL`]
+    pub fn with_base_url<T: Into<String>>(mut self, base_url: T) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Set timeout of each request, defaults to [`DEFAULT_API_TIMEOUT`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{path}", self.base_url))
+            .header(ACCEPT, "application/json")
+            .timeout(self.timeout)
+    }
+}
+
+/// Credentials of Cloudflare API
+#[derive(Clone, Eq, PartialEq)]
+pub enum Credentials {
+    /// API token, scoped to permissions and zones
+    Token(String),
+    /// Global API key with email of account, for accounts which cannot issue API tokens
+    GlobalKey {
+        /// Email of account
+        email: String,
+        /// Global API key
+        key: String,
+    },
+}
+
+impl Credentials {
+    /// Creates [`Credentials::GlobalKey`] of account by email
+    pub fn global_key<T: Into<String>, U: Into<String>>(email: T, key: U) -> Self {
+        Self::GlobalKey {
+            email: email.into(),
+            key: key.into(),
+        }
+    }
+
+    fn authenticate(&self, req: RequestBuilder) -> RequestBuilder {
+        match self {
+            Self::Token(token) => req.bearer_auth(token),
+            Self::GlobalKey { email, key } => {
+                req.header("X-Auth-Email", email).header("X-Auth-Key", key)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        // keep secrets out of logs
+        match self {
+            Self::Token(_) => f.write_str("Token(***)"),
+            Self::GlobalKey { email, .. } => f
+                .debug_struct("GlobalKey")
+                .field("email", email)
+                .field("key", &"***")
+                .finish(),
+        }
+    }
+}
+
+impl From<String> for Credentials {
+    fn from(token: String) -> Self {
+        Self::Token(token)
+    }
+}
+
+impl From<&str> for Credentials {
+    fn from(token: &str) -> Self {
+        Self::Token(token.to_string())
+    }
+}
+
+/// Status of API token, as verified by Cloudflare
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TokenStatus {
+    /// Identifier of token
+    pub id: String,
+    /// Status e.g. `active`, `disabled` or `expired`
+    pub status: String,
+    /// Time before which token is not valid, if any
+    #[serde(default)]
+    pub not_before: Option<String>,
+    /// Time after which token is not valid, if any
+    #[serde(default)]
+    pub expires_on: Option<String>,
+    /// Permission groups granted to token e.g. `DNS Write`,
+    /// `None` if token is not allowed to read its own permissions
+    #[serde(default)]
+    pub permissions: Option<Vec<String>>,
+}
+
+impl TokenStatus {
+    /// Whether token is valid
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+}
+
+/// Policies of API token
+#[derive(Debug, Deserialize)]
+struct TokenDetails {
+    #[serde(default)]
+    policies: Vec<TokenPolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenPolicy {
+    effect: String,
+    #[serde(default)]
+    permission_groups: Vec<PermissionGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PermissionGroup {
+    name: String,
+}
+
+/// Zone accessible to API token
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ZoneInfo {
+    /// Identifier of zone
+    pub id: String,
+    /// Zone name
+    pub name: String,
+    /// Status e.g. `active` or `pending`
+    pub status: String,
+}
+
+/// DNS record of any type in a zone
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RecordInfo {
+    /// Identifier of DNS record
+    pub id: String,
+    /// DNS record name
+    pub name: String,
+    /// DNS record type e.g. `A`, `CNAME` or `MX`
+    #[serde(rename = "type")]
+    pub record_type: String,
+    /// Content of DNS record
+    #[serde(default)]
+    pub content: String,
+    /// Whether DNS record is proxied by Cloudflare, if it may be proxied
+    #[serde(default)]
+    pub proxied: Option<bool>,
+    /// Time to live, 1 for automatic
+    pub ttl: u32,
+}
+
+/// Cloudflare DNS API authenticated with API token or global API key
+pub struct CloudflareProvider {
+    credentials: Credentials,
+    client: ApiClient,
+}
+
+impl std::fmt::Debug for CloudflareProvider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("CloudflareProvider")
+            .field("credentials", &self.credentials)
+            .field("client", &self.client)
+            .finish()
+    }
+}
+
+impl CloudflareProvider {
+    /// Creates a [`CloudflareProvider`] with Cloudflare API token or other [`Credentials`]
+    pub fn new<T: Into<Credentials>>(credentials: T) -> Self {
+        Self {
+            credentials: credentials.into(),
+            client: ApiClient::default(),
+        }
+    }
+
+    /// Set HTTP client, e.g. one shared with providers of other zones
+    pub fn with_client(mut self, client: ApiClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Send request authenticated with credentials
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T> {
+        send(req, &self.credentials).await
+    }
+
+    /// Fetch all pages of list endpoint
+    async fn list<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<T>> {
+        list(&self.client, &self.credentials, path, query).await
+    }
+
+    async fn get_zone_identifier(&self, zone: &str) -> Result<String> {
+        let tmr = stimer!(Level::Debug; "FETCH_ZONE", "zone={zone}");
+        let zones: Vec<Zone> = self.list("/zones", &[("name", zone)]).await?;
+        let id = match zones.iter().find(|z| z.name.eq_ignore_ascii_case(zone)) {
+            Some(zone) => zone.id.to_string(),
+            None => {
+                return Err(Error::ZoneNotFound {
+                    zone: zone.to_string(),
+                })
+            }
+        };
+        finish!(tmr, "zone_id={id}");
+        Ok(id)
+    }
+
+    async fn get_dns_record(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+        record_type: RecordType,
+    ) -> Result<Option<Record>> {
+        let path = format!("/zones/{zone_id}/dns_records");
+        let record_type = record_type.to_string();
+        let tmr = stimer!(Level::Debug; "FETCH_DNS_RECORD", "zone_id={zone_id}");
+        let records: Vec<DnsRecord> = self
+            .list(&path, &[("name", record_name), ("type", &record_type)])
+            .await
+            .map_err(|e| not_found(e, || zone_not_found(zone_id)))?;
+        let record = records.into_iter().next();
+        finish!(tmr, "id={:?}", record.as_ref().map(|r| &r.id));
+        Ok(record.map(Record::from))
+    }
+
+    async fn list_dns_records(
+        &self,
+        zone_id: &str,
+        record_type: RecordType,
+    ) -> Result<Vec<Record>> {
+        let path = format!("/zones/{zone_id}/dns_records");
+        let record_type = record_type.to_string();
+        let tmr = stimer!(Level::Debug; "LIST_DNS_RECORDS", "zone_id={zone_id},type={record_type}");
+        let records: Vec<DnsRecord> = self
+            .list(&path, &[("type", &record_type)])
+            .await
+            .map_err(|e| not_found(e, || zone_not_found(zone_id)))?;
+        finish!(tmr, "count={}", records.len());
+        Ok(records.into_iter().map(Record::from).collect())
+    }
+
+    async fn create_dns_record(
+        &self,
+        zone_id: &str,
+        dns_record_name: &str,
+        current_ip: IpAddr,
+        options: CreateOptions,
+    ) -> Result<String> {
+        let path = format!("/zones/{zone_id}/dns_records");
+        let req = self
+            .client
+            .request(Method::POST, &path)
+            .json(&serde_json::json!({
+                "type": RecordType::of(&current_ip).to_string(),
+                "name": dns_record_name,
+                "content": current_ip,
+                "ttl": options.ttl,
+                "proxied": options.proxied,
+            }));
+        let tmr =
+            stimer!(Level::Debug; "CREATE_DNS_RECORD", "zone_id={zone_id},name={dns_record_name}");
+        let res: ApiSuccess<DnsRecord> = self
+            .send(req)
+            .await
+            .map_err(|e| not_found(e, || zone_not_found(zone_id)))?;
+        let identifier = res.result.id;
+        finish!(tmr, "id={identifier}");
+        Ok(identifier)
+    }
+
+    async fn update_dns_record(
+        &self,
+        zone_id: &str,
+        dns_record_id: &str,
+        dns_record_name: &str,
+        current_ip: IpAddr,
+        options: RecordOptions,
+    ) -> Result<()> {
+        let path = format!("/zones/{zone_id}/dns_records/{dns_record_id}");
+        let mut body = serde_json::json!({
+            "type": RecordType::of(&current_ip).to_string(),
+            "name": dns_record_name,
+            "content": current_ip,
+        });
+        if let Some(ttl) = options.ttl {
+            body["ttl"] = ttl.into();
+        }
+        if let Some(proxied) = options.proxied {
+            body["proxied"] = proxied.into();
+        }
+        // PATCH keeps settings e.g. proxied, TTL and comment which are not sent
+        let req = self.client.request(Method::PATCH, &path).json(&body);
+        let tmr = stimer!(Level::Debug; "UPDATE_DNS_RECORD", "zone_id={zone_id},dns_record_id={dns_record_id}");
+        let res: ApiSuccess<DnsRecord> = self.send(req).await.map_err(|e| {
+            not_found(e, || Error::RecordNotFound {
+                name: dns_record_name.to_string(),
+                record_type: RecordType::of(&current_ip),
+            })
+        })?;
+        let content = content_of(&res.result.content);
+        let (ttl, proxied) = (res.result.ttl, res.result.proxied);
+        finish!(tmr, "content={content},ttl={ttl},proxied={proxied}");
+        Ok(())
+    }
+}
+
+impl CloudflareProvider {
+    /// Verify API token, with its permissions if it is allowed to read them.
+    /// Global API keys cannot be verified
+    pub async fn verify_token(&self) -> Result<TokenStatus> {
+        let req = self.client.request(Method::GET, "/user/tokens/verify");
+        let res: ApiSuccess<TokenStatus> = self.send(req).await?;
+        let mut status = res.result;
+        let path = format!("/user/tokens/{}", status.id);
+        let req = self.client.request(Method::GET, &path);
+        status.permissions = match self.send::<ApiSuccess<TokenDetails>>(req).await {
+            Ok(res) => Some(
+                res.result
+                    .policies
+                    .into_iter()
+                    .filter(|p| p.effect == "allow")
+                    .flat_map(|p| p.permission_groups)
+                    .map(|g| g.name)
+                    .collect(),
+            ),
+            Err(e @ Error::Auth { .. }) => {
+                debug!("cannot read permissions of token: {e}");
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(status)
+    }
+
+    /// List all zones accessible to API token
+    pub async fn zones(&self) -> Result<Vec<ZoneInfo>> {
+        self.list("/zones", &[]).await
+    }
+
+    /// List all DNS records of any type in zone by name
+    pub async fn records(&self, zone: &str) -> Result<Vec<RecordInfo>> {
+        let zone_id = self.get_zone_identifier(zone).await?;
+        let path = format!("/zones/{zone_id}/dns_records");
+        self.list(&path, &[])
+            .await
+            .map_err(|e| not_found(e, || zone_not_found(zone)))
+    }
+}
+
+impl DnsProvider for CloudflareProvider {
+    fn zone_id<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<String>> {
+        self.get_zone_identifier(zone).boxed()
+    }
+
+    fn get_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Option<Record>>> {
+        self.get_dns_record(zone_id, name, record_type).boxed()
+    }
+
+    fn list_records<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Vec<Record>>> {
+        self.list_dns_records(zone_id, record_type).boxed()
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: CreateOptions,
+    ) -> BoxFuture<'a, Result<String>> {
+        self.create_dns_record(zone_id, name, ip, options).boxed()
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        record_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: RecordOptions,
+    ) -> BoxFuture<'a, Result<()>> {
+        self.update_dns_record(zone_id, record_id, name, ip, options)
+            .boxed()
+    }
+}
+
+/// Account list e.g. of IP addresses
+#[derive(Debug, Deserialize)]
+struct AccountList {
+    id: String,
+    name: String,
+    kind: String,
+}
+
+/// Item of IP list
+#[derive(Debug, Deserialize)]
+struct AccountListItem {
+    id: String,
+    ip: String,
+    comment: Option<String>,
+}
+
+/// Cursor pagination of list items in `result_info`
+#[derive(Debug, Deserialize)]
+struct CursorInfo {
+    cursors: Option<Cursors>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cursors {
+    after: Option<String>,
+}
+
+/// Operation on list items, which runs asynchronously
+#[derive(Debug, Deserialize)]
+struct Operation {
+    operation_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkOperation {
+    status: String,
+    error: Option<String>,
+}
+
+/// Cloudflare IP list of an account e.g. referenced by WAF rules,
+/// authenticated with API token or global API key
+pub struct CloudflareIpList {
+    credentials: Credentials,
+    client: ApiClient,
+    account_id: String,
+    name: String,
+    comment: String,
+    /// Identifier of the list, resolved by name once
+    list_id: OnceCell<String>,
+}
+
+impl std::fmt::Debug for CloudflareIpList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("CloudflareIpList")
+            .field("credentials", &self.credentials)
+            .field("client", &self.client)
+            .field("account_id", &self.account_id)
+            .field("name", &self.name)
+            .field("comment", &self.comment)
+            .field("list_id", &self.list_id.get())
+            .finish()
+    }
+}
+
+impl CloudflareIpList {
+    /// Creates a [`CloudflareIpList`] of IP list by name in account
+    pub fn new<T, U, V>(credentials: T, account_id: U, name: V) -> Self
+    where
+        T: Into<Credentials>,
+        U: Into<String>,
+        V: Into<String>,
+    {
+        Self {
+            credentials: credentials.into(),
+            client: ApiClient::default(),
+            account_id: account_id.into(),
+            name: name.into(),
+            comment: DEFAULT_LIST_ITEM_COMMENT.to_string(),
+            list_id: OnceCell::new(),
+        }
+    }
+
+    /// Set HTTP client, e.g. one shared with providers of zones
+    pub fn with_client(mut self, client: ApiClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Set comment of items added, defaults to [`DEFAULT_LIST_ITEM_COMMENT`].
+    /// Items with the comment are replaced on change, so instances of cdu sharing
+    /// a list need distinct comments
+    pub fn with_comment<T: Into<String>>(mut self, comment: T) -> Self {
+        self.comment = comment.into();
+        self
+    }
+
+    async fn list_id(&self) -> Result<&str> {
+        let id = self
+            .list_id
+            .get_or_try_init(|| async {
+                let path = format!("/accounts/{}/rules/lists", self.account_id);
+                let tmr = stimer!(Level::Debug; "FETCH_IP_LIST", "name={}", self.name);
+                let lists: Vec<AccountList> =
+                    list(&self.client, &self.credentials, &path, &[]).await?;
+                let id = lists
+                    .into_iter()
+                    .find(|l| l.kind == "ip" && l.name == self.name)
+                    .map(|l| l.id)
+                    .ok_or_else(|| Error::ListNotFound {
+                        name: self.name.clone(),
+                    })?;
+                finish!(tmr, "list_id={id}");
+                Ok::<_, Error>(id)
+            })
+            .await?;
+        Ok(id)
+    }
+
+    async fn list_items(&self) -> Result<Vec<ListItem>> {
+        let list_id = self.list_id().await?;
+        let path = format!("/accounts/{}/rules/lists/{list_id}/items", self.account_id);
+        let tmr = stimer!(Level::Debug; "LIST_IP_LIST_ITEMS", "list_id={list_id}");
+        let mut items = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut req = self.client.request(Method::GET, &path);
+            if let Some(cursor) = &cursor {
+                req = req.query(&[("cursor", cursor)]);
+            }
+            let res: ApiSuccess<Vec<AccountListItem>> = send(req, &self.credentials).await?;
+            items.extend(res.result.into_iter().map(|i| ListItem {
+                id: i.id,
+                ip: i.ip,
+                comment: i.comment,
+            }));
+            cursor = res
+                .result_info
+                .and_then(|info| serde_json::from_value::<CursorInfo>(info).ok())
+                .and_then(|info| info.cursors)
+                .and_then(|cursors| cursors.after);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        finish!(tmr, "count={}", items.len());
+        Ok(items)
+    }
+
+    /// Wait for bulk operation on list items to complete
+    async fn wait(&self, operation_id: &str) -> Result<()> {
+        let path = format!(
+            "/accounts/{}/rules/lists/bulk_operations/{operation_id}",
+            self.account_id
+        );
+        for _ in 0..BULK_OPERATION_POLLS {
+            let req = self.client.request(Method::GET, &path);
+            let res: ApiSuccess<BulkOperation> = send(req, &self.credentials).await?;
+            match res.result.status.as_str() {
+                "completed" => return Ok(()),
+                "failed" => {
+                    return Err(Error::Api {
+                        status: None,
+                        message: format!(
+                            "operation {operation_id} on IP list {} failed: {}",
+                            self.name,
+                            res.result.error.unwrap_or_default()
+                        ),
+                        retry_after: None,
+                    })
+                }
+                _ => tokio::time::sleep(BULK_OPERATION_POLL_DELAY).await,
+            }
+        }
+        Err(Error::Api {
+            status: None,
+            message: format!(
+                "operation {operation_id} on IP list {} is still pending",
+                self.name
+            ),
+            retry_after: None,
+        })
+    }
+
+    async fn add_list_item(&self, ip: IpAddr) -> Result<()> {
+        let list_id = self.list_id().await?;
+        let path = format!("/accounts/{}/rules/lists/{list_id}/items", self.account_id);
+        let req = self
+            .client
+            .request(Method::POST, &path)
+            .json(&serde_json::json!([{
+                "ip": self.item_of(ip),
+                "comment": self.comment,
+            }]));
+        let tmr = stimer!(Level::Debug; "ADD_IP_LIST_ITEM", "list_id={list_id},ip={ip}");
+        let res: ApiSuccess<Operation> = send(req, &self.credentials).await?;
+        self.wait(&res.result.operation_id).await?;
+        finish!(tmr);
+        Ok(())
+    }
+
+    async fn remove_list_items(&self, ids: &[String]) -> Result<()> {
+        let list_id = self.list_id().await?;
+        let path = format!("/accounts/{}/rules/lists/{list_id}/items", self.account_id);
+        let items: Vec<serde_json::Value> = ids
+            .iter()
+            .map(|id| serde_json::json!({ "id": id }))
+            .collect();
+        let req = self
+            .client
+            .request(Method::DELETE, &path)
+            .json(&serde_json::json!({ "items": items }));
+        let tmr = stimer!(Level::Debug; "REMOVE_IP_LIST_ITEMS", "list_id={list_id},ids={ids:?}");
+        let res: ApiSuccess<Operation> = send(req, &self.credentials).await?;
+        self.wait(&res.result.operation_id).await?;
+        finish!(tmr);
+        Ok(())
+    }
+}
+
+impl IpList for CloudflareIpList {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// Cloudflare IP lists hold IPv6 addresses as /64 ranges
+    fn item_of(&self, ip: IpAddr) -> String {
+        match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(_) => IpNet::new(ip, 64)
+                .map(|net| net.trunc().to_string())
+                .unwrap_or_else(|_| ip.to_string()),
+        }
+    }
+
+    fn items(&self) -> BoxFuture<'_, Result<Vec<ListItem>>> {
+        self.list_items().boxed()
+    }
+
+    fn add_item(&self, ip: IpAddr) -> BoxFuture<'_, Result<()>> {
+        self.add_list_item(ip).boxed()
+    }
+
+    fn remove_items<'a>(&'a self, ids: &'a [String]) -> BoxFuture<'a, Result<()>> {
+        self.remove_list_items(ids).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mockito::{mock, Matcher};
+
+    use crate::Retry;
+
+    fn provider() -> CloudflareProvider {
+        let client =
+            ApiClient::default().with_base_url(format!("{}/client/v4", mockito::server_url()));
+        CloudflareProvider::new("token").with_client(client)
+    }
+
+    #[tokio::test]
+    async fn t_get_record_identifier() {
+        let _m = mock("GET", "/client/v4/zones/1/dns_records")
+            .match_query(Matcher::UrlEncoded("name".into(), "record".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"meta":{"auto_added":false},"locked":false,"name":"record","ttl":0,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"0.0.0.0","type":"A","id":"2","proxied":false,"zone_name":"zone"}],"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let record = provider
+            .get_record("1", "record", RecordType::A)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("2", record.id);
+        assert_eq!("record", record.name);
+        assert_eq!("0.0.0.0", record.content);
+        assert_eq!(Some(false), record.proxied);
+    }
+
+    #[tokio::test]
+    async fn t_get_record_identifier_not_found() {
+        let _m = mock("GET", "/client/v4/zones/1/dns_records")
+            .match_query(Matcher::UrlEncoded("name".into(), "missing".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[],"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let record = provider
+            .get_record("1", "missing", RecordType::A)
+            .await
+            .unwrap();
+        assert!(record.is_none());
+    }
+
+    #[tokio::test]
+    async fn t_list_records() {
+        let _m1 = mock("GET", "/client/v4/zones/1/dns_records")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("type".into(), "A".into()),
+                Matcher::UrlEncoded("page".into(), "1".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"meta":{"auto_added":false},"locked":false,"name":"a.x.com","ttl":1,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"1.1.1.1","type":"A","id":"2","proxied":false,"zone_name":"x.com"}],"result_info":{"page":1,"per_page":1,"count":1,"total_count":2,"total_pages":2},"messages":[],"errors":[]}"#)
+            .create();
+        let _m2 = mock("GET", "/client/v4/zones/1/dns_records")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("type".into(), "A".into()),
+                Matcher::UrlEncoded("page".into(), "2".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"meta":{"auto_added":false},"locked":false,"name":"b.x.com","ttl":1,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"1.1.1.2","type":"A","id":"3","proxied":false,"zone_name":"x.com"}],"result_info":{"page":2,"per_page":1,"count":1,"total_count":2,"total_pages":2},"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let records = provider.list_records("1", RecordType::A).await.unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["a.x.com", "b.x.com"], names);
+        assert_eq!("1.1.1.2", records[1].content);
+    }
+
+    #[tokio::test]
+    async fn t_get_zone_identifier_second_page() {
+        let _m1 = mock("GET", "/client/v4/zones")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("name".into(), "y.com".into()),
+                Matcher::UrlEncoded("page".into(), "1".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"1","name":"x.y.com","account":{"id":"2","name":"a"},"created_on":"1970-01-01T00:00:00Z","development_mode":0,"meta":{"custom_certificate_quota":0,"page_rule_quota":0,"phishing_detected":false,"multiple_railguns_allowed":false},"modified_on":"1970-01-01T00:00:00Z","name_servers":[],"owner":{"type":"user","email":"","id":""},"paused":false,"permissions":[],"status":"active","type":"full"}],"result_info":{"page":1,"per_page":1,"count":1,"total_count":2,"total_pages":2},"messages":[],"errors":[]}"#)
+            .create();
+        let _m2 = mock("GET", "/client/v4/zones")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("name".into(), "y.com".into()),
+                Matcher::UrlEncoded("page".into(), "2".into()),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"3","name":"y.com","account":{"id":"2","name":"a"},"created_on":"1970-01-01T00:00:00Z","development_mode":0,"meta":{"custom_certificate_quota":0,"page_rule_quota":0,"phishing_detected":false,"multiple_railguns_allowed":false},"modified_on":"1970-01-01T00:00:00Z","name_servers":[],"owner":{"type":"user","email":"","id":""},"paused":false,"permissions":[],"status":"active","type":"full"}],"result_info":{"page":2,"per_page":1,"count":1,"total_count":2,"total_pages":2},"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        assert_eq!("3", provider.zone_id("y.com").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn t_create_dns_record() {
+        let _m = mock("POST", "/client/v4/zones/1/dns_records")
+            .match_body(r#"{"content":"127.0.0.1","name":"new","proxied":true,"ttl":300,"type":"A"}"#)
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"meta":{"auto_added":false},"locked":false,"name":"new","ttl":300,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":true,"content":"127.0.0.1","type":"A","id":"4","proxied":true,"zone_name":"zone"},"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let options = CreateOptions {
+            ttl: 300,
+            proxied: true,
+        };
+        let id = provider
+            .create_record("1", "new", "127.0.0.1".parse().unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!("4", id);
+    }
+
+    #[tokio::test]
+    async fn t_get_zone_identifier() {
+        let _m = mock("GET", "/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("name".into(), "zone".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"1","name":"zone","account":{"id":"2","name":"a"},"created_on":"1970-01-01T00:00:00Z","development_mode":0,"meta":{"custom_certificate_quota":0,"page_rule_quota":0,"phishing_detected":false,"multiple_railguns_allowed":false},"modified_on":"1970-01-01T00:00:00Z","name_servers":[],"owner":{"type":"user","email":"","id":""},"paused":false,"permissions":[],"status":"active","type":"full"}],"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let zone_identifier = provider.zone_id("zone").await.unwrap();
+        assert_eq!(zone_identifier, "1");
+    }
+
+    #[tokio::test]
+    async fn t_update_dns_record() {
+        let _m2 = mock("PATCH", "/client/v4/zones/1/dns_records/2")
+            .match_body(r#"{"content":"127.0.0.1","name":"record","type":"A"}"#)
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"meta":{"auto_added":false},"locked":false,"name":"record","ttl":0,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"0.0.0.0","type":"A","id":"2","proxied":false,"zone_name":"zone"},"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        provider
+            .update_record(
+                "1",
+                "2",
+                "record",
+                "127.0.0.1".parse().unwrap(),
+                RecordOptions::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn t_update_dns_record_aaaa() {
+        let _m = mock("PATCH", "/client/v4/zones/1/dns_records/3")
+            .match_body(r#"{"content":"::1","name":"record","proxied":true,"ttl":300,"type":"AAAA"}"#)
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"meta":{"auto_added":false},"locked":false,"name":"record","ttl":0,"zone_id":"1","modified_on":"1970-01-01T00:00:00Z","created_on":"1970-01-01T00:00:00Z","proxiable":false,"content":"::","type":"AAAA","id":"3","proxied":false,"zone_name":"zone"},"messages":[],"errors":[]}"#)
+            .create();
+        let provider = provider();
+        let options = RecordOptions {
+            ttl: Some(300),
+            proxied: Some(true),
+        };
+        provider
+            .update_record("1", "3", "record", "::1".parse().unwrap(), options)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn t_is_not_found() {
+        let _m = mock("PATCH", "/client/v4/zones/1/dns_records/404")
+            .with_status(404)
+            .with_body(r#"{"success":false,"result":null,"messages":[],"errors":[{"code":81044,"message":"Record does not exist."}]}"#)
+            .create();
+        let provider = provider();
+        let e = provider
+            .update_record(
+                "1",
+                "404",
+                "record",
+                "127.0.0.1".parse().unwrap(),
+                RecordOptions::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(e.is_not_found());
+        assert_eq!("DNS record not found: record (A)", e.to_string());
+    }
+
+    #[tokio::test]
+    async fn t_api_client() {
+        let _m = mock("GET", "/base/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("name".into(), "slow".into()))
+            .with_status(200)
+            .with_body_from_fn(|w| {
+                std::thread::sleep(Duration::from_millis(500));
+                w.write_all(br#"{"success":true,"result":[],"messages":[],"errors":[]}"#)
+            })
+            .create();
+        // trailing slash of base URL is trimmed
+        let client = ApiClient::default()
+            .with_base_url(format!("{}/base/client/v4/", mockito::server_url()))
+            .with_timeout(Duration::from_millis(100));
+        let provider = CloudflareProvider::new("token").with_client(client.clone());
+        let e = provider.zone_id("slow").await.unwrap_err();
+        assert!(matches!(e, Error::Transport { .. }));
+        assert!(e.is_retryable());
+
+        let provider = CloudflareProvider::new("token")
+            .with_client(client.with_timeout(Duration::from_secs(5)));
+        let e = provider.zone_id("slow").await.unwrap_err();
+        assert_eq!("zone not found: slow", e.to_string());
+    }
+
+    #[test]
+    fn t_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            parse_retry_after("120", now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now)
+        );
+        assert_eq!(
+            Some(Duration::ZERO),
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now)
+        );
+        assert_eq!(None, parse_retry_after("soon", now));
+    }
+
+    #[tokio::test]
+    async fn t_rate_limited() {
+        let _m = mock("GET", "/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("name".into(), "limited".into()))
+            .with_status(429)
+            .with_header("retry-after", "30")
+            .with_body(r#"{"success":false,"result":null,"messages":[],"errors":[{"code":971,"message":"Please wait and consider throttling your request speed"}]}"#)
+            .create();
+        let provider = provider();
+        let e = provider.zone_id("limited").await.unwrap_err();
+        let after = Duration::from_secs(30);
+        assert!(matches!(e, Error::RateLimited { retry_after: Some(d) } if d == after));
+        assert_eq!(Some(Retry::After(after)), e.retry());
+    }
+
+    #[tokio::test]
+    async fn t_auth() {
+        let _m = mock("GET", "/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("name".into(), "denied".into()))
+            .with_status(403)
+            .with_body(r#"{"success":false,"result":null,"messages":[],"errors":[{"code":9109,"message":"Invalid access token"}]}"#)
+            .create();
+        let provider = provider();
+        let e = provider.zone_id("denied").await.unwrap_err();
+        assert!(matches!(e, Error::Auth { .. }));
+        assert!(!e.is_retryable());
+        assert_eq!(
+            "DNS provider refuses credentials: HTTP 403 Forbidden: 9109 Invalid access token",
+            e.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn t_server_error() {
+        let _m = mock("PATCH", "/client/v4/zones/1/dns_records/503")
+            .with_status(503)
+            .with_body("Service Unavailable")
+            .create();
+        let provider = provider();
+        let e = provider
+            .update_record(
+                "1",
+                "503",
+                "record",
+                "127.0.0.1".parse().unwrap(),
+                RecordOptions::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            e,
+            Error::Api {
+                status: Some(503),
+                retry_after: None,
+                ..
+            }
+        ));
+        assert_eq!(Some(Retry::Backoff), e.retry());
+        assert!(!e.is_not_found());
+    }
+
+    #[tokio::test]
+    async fn t_global_key() {
+        let _m = mock("GET", "/global/client/v4/zones")
+            .match_query(Matcher::Any)
+            .match_header("X-Auth-Email", "a@x.com")
+            .match_header("X-Auth-Key", "key")
+            .match_header("Authorization", Matcher::Missing)
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"1","name":"x.com","status":"active"}],"messages":[],"errors":[]}"#)
+            .create();
+        let client = ApiClient::default()
+            .with_base_url(format!("{}/global/client/v4", mockito::server_url()));
+        let credentials = Credentials::global_key("a@x.com", "key");
+        assert_eq!(
+            r#"GlobalKey { email: "a@x.com", key: "***" }"#,
+            format!("{credentials:?}")
+        );
+        assert_eq!("Token(***)", format!("{:?}", Credentials::from("token")));
+        let provider = CloudflareProvider::new(credentials).with_client(client);
+        let zones = provider.zones().await.unwrap();
+        assert_eq!("x.com", zones[0].name);
+    }
+
+    #[tokio::test]
+    async fn t_verify_token() {
+        let _m1 = mock("GET", "/verify/client/v4/user/tokens/verify")
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"id":"t1","status":"active","expires_on":"2030-01-01T00:00:00Z"},"messages":[],"errors":[]}"#)
+            .create();
+        let _m2 = mock("GET", "/verify/client/v4/user/tokens/t1")
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"id":"t1","policies":[{"effect":"allow","permission_groups":[{"id":"g1","name":"DNS Write"},{"id":"g2","name":"Zone Read"}]},{"effect":"deny","permission_groups":[{"id":"g3","name":"Zone Settings Write"}]}]},"messages":[],"errors":[]}"#)
+            .create();
+        let _m3 = mock("GET", "/limited/client/v4/user/tokens/verify")
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"id":"t2","status":"disabled"},"messages":[],"errors":[]}"#)
+            .create();
+        let _m4 = mock("GET", "/limited/client/v4/user/tokens/t2")
+            .with_status(403)
+            .with_body(r#"{"success":false,"result":null,"messages":[],"errors":[{"code":9109,"message":"Unauthorized to access requested resource"}]}"#)
+            .create();
+
+        let client = ApiClient::default()
+            .with_base_url(format!("{}/verify/client/v4", mockito::server_url()));
+        let provider = CloudflareProvider::new("token").with_client(client);
+        let status = provider.verify_token().await.unwrap();
+        assert!(status.is_active());
+        assert_eq!(Some("2030-01-01T00:00:00Z"), status.expires_on.as_deref());
+        assert_eq!(
+            Some(vec!["DNS Write".to_string(), "Zone Read".to_string()]),
+            status.permissions
+        );
+
+        let client = ApiClient::default()
+            .with_base_url(format!("{}/limited/client/v4", mockito::server_url()));
+        let provider = CloudflareProvider::new("token").with_client(client);
+        let status = provider.verify_token().await.unwrap();
+        assert!(!status.is_active());
+        assert_eq!(None, status.permissions);
+    }
+
+    #[tokio::test]
+    async fn t_zones_and_records() {
+        let _m1 = mock("GET", "/inspect/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"1","name":"x.com","status":"active","paused":false},{"id":"2","name":"y.com","status":"pending","paused":false}],"result_info":{"page":1,"per_page":2,"count":2,"total_count":2,"total_pages":1},"messages":[],"errors":[]}"#)
+            .create();
+        let _m2 = mock("GET", "/inspect/client/v4/zones")
+            .match_query(Matcher::UrlEncoded("name".into(), "x.com".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"1","name":"x.com","account":{"id":"2","name":"a"},"created_on":"1970-01-01T00:00:00Z","development_mode":0,"meta":{"custom_certificate_quota":0,"page_rule_quota":0,"phishing_detected":false,"multiple_railguns_allowed":false},"modified_on":"1970-01-01T00:00:00Z","name_servers":[],"owner":{"type":"user","email":"","id":""},"paused":false,"permissions":[],"status":"active","type":"full"}],"messages":[],"errors":[]}"#)
+            .create();
+        let _m3 = mock("GET", "/inspect/client/v4/zones/1/dns_records")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"r1","name":"x.com","type":"A","content":"1.1.1.1","proxied":true,"proxiable":true,"ttl":1},{"id":"r2","name":"x.com","type":"MX","content":"mail.x.com","proxiable":false,"priority":10,"ttl":3600}],"messages":[],"errors":[]}"#)
+            .create();
+
+        let client = ApiClient::default()
+            .with_base_url(format!("{}/inspect/client/v4", mockito::server_url()));
+        let provider = CloudflareProvider::new("token").with_client(client);
+        let zones = provider.zones().await.unwrap();
+        assert_eq!(
+            vec![
+                ZoneInfo {
+                    id: "1".into(),
+                    name: "x.com".into(),
+                    status: "active".into(),
+                },
+                ZoneInfo {
+                    id: "2".into(),
+                    name: "y.com".into(),
+                    status: "pending".into(),
+                },
+            ],
+            zones
+        );
+        let records = provider.records("x.com").await.unwrap();
+        assert_eq!(
+            vec![
+                RecordInfo {
+                    id: "r1".into(),
+                    name: "x.com".into(),
+                    record_type: "A".into(),
+                    content: "1.1.1.1".into(),
+                    proxied: Some(true),
+                    ttl: 1,
+                },
+                RecordInfo {
+                    id: "r2".into(),
+                    name: "x.com".into(),
+                    record_type: "MX".into(),
+                    content: "mail.x.com".into(),
+                    proxied: None,
+                    ttl: 3600,
+                },
+            ],
+            records
+        );
+    }
+
+    #[tokio::test]
+    async fn t_ip_list() {
+        let m1 = mock("GET", "/client/v4/accounts/account/rules/lists")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"l0","name":"home","kind":"asn"}],"result_info":{"page":1,"total_pages":2},"messages":[],"errors":[]}"#)
+            .expect(2)
+            .create();
+        let m1_2 = mock("GET", "/client/v4/accounts/account/rules/lists")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"l1","name":"office","kind":"asn"},{"id":"l2","name":"office","kind":"ip"}],"result_info":{"page":2,"total_pages":2},"messages":[],"errors":[]}"#)
+            .expect(2)
+            .create();
+        let _m2 = mock("GET", "/client/v4/accounts/account/rules/lists/l2/items")
+            .match_query(Matcher::Missing)
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"i1","ip":"1.1.1.1","comment":"cdu"}],"result_info":{"cursors":{"after":"next"}},"messages":[],"errors":[]}"#)
+            .create();
+        let _m3 = mock("GET", "/client/v4/accounts/account/rules/lists/l2/items")
+            .match_query(Matcher::UrlEncoded("cursor".into(), "next".into()))
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":[{"id":"i2","ip":"2001:db8::/64"}],"result_info":{"cursors":{}},"messages":[],"errors":[]}"#)
+            .create();
+        let _m4 = mock("POST", "/client/v4/accounts/account/rules/lists/l2/items")
+            .match_body(Matcher::Json(
+                serde_json::json!([{"ip": "2001:db8:1::/64", "comment": "cdu x.com"}]),
+            ))
+            .with_status(200)
+            .with_body(
+                r#"{"success":true,"result":{"operation_id":"o1"},"messages":[],"errors":[]}"#,
+            )
+            .create();
+        let _m5 = mock("DELETE", "/client/v4/accounts/account/rules/lists/l2/items")
+            .match_body(Matcher::Json(serde_json::json!({"items": [{"id": "i2"}]})))
+            .with_status(200)
+            .with_body(
+                r#"{"success":true,"result":{"operation_id":"o2"},"messages":[],"errors":[]}"#,
+            )
+            .create();
+        let _m6 = mock("GET", "/client/v4/accounts/account/rules/lists/bulk_operations/o1")
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"id":"o1","status":"completed"},"messages":[],"errors":[]}"#)
+            .create();
+        let _m7 = mock("GET", "/client/v4/accounts/account/rules/lists/bulk_operations/o2")
+            .with_status(200)
+            .with_body(r#"{"success":true,"result":{"id":"o2","status":"failed","error":"item not found"},"messages":[],"errors":[]}"#)
+            .create();
+
+        let client =
+            ApiClient::default().with_base_url(format!("{}/client/v4", mockito::server_url()));
+        let list = CloudflareIpList::new("token", "account", "office")
+            .with_client(client)
+            .with_comment("cdu x.com");
+        assert_eq!("1.1.1.1", list.item_of("1.1.1.1".parse().unwrap()));
+        assert_eq!(
+            "2001:db8:1::/64",
+            list.item_of("2001:db8:1::1".parse().unwrap())
+        );
+
+        let items = list.items().await.unwrap();
+        assert_eq!(
+            vec![
+                ListItem {
+                    id: "i1".into(),
+                    ip: "1.1.1.1".into(),
+                    comment: Some("cdu".into()),
+                },
+                ListItem {
+                    id: "i2".into(),
+                    ip: "2001:db8::/64".into(),
+                    comment: None,
+                },
+            ],
+            items
+        );
+        list.add_item("2001:db8:1::1".parse().unwrap())
+            .await
+            .unwrap();
+        let e = list.remove_items(&["i2".to_string()]).await.unwrap_err();
+        assert_eq!(
+            "DNS provider fails: operation o2 on IP list office failed: item not found",
+            e.to_string()
+        );
+
+        let list = CloudflareIpList::new("token", "account", "home").with_client(
+            ApiClient::default().with_base_url(format!("{}/client/v4", mockito::server_url())),
+        );
+        let e = list.items().await.unwrap_err();
+        assert!(matches!(e, Error::ListNotFound { .. }));
+        assert_eq!("IP list not found: home", e.to_string());
+        // list found on the second page is looked up once for all calls
+        m1.assert();
+        m1_2.assert();
+    }
+}
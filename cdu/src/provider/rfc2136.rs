@@ -0,0 +1,680 @@
+use std::fmt::{Formatter, Result as FmtResult};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine as _;
+use futures::future::BoxFuture;
+use futures::FutureExt as _;
+use hickory_client::client::{AsyncClient, ClientHandle};
+use hickory_client::op::{Message, MessageType, OpCode, Query, ResponseCode, UpdateMessage as _};
+use hickory_client::proto::error::ProtoError;
+use hickory_client::proto::rr::dnssec::tsig::TSigner;
+use hickory_client::proto::xfer::{DnsHandle as _, FirstAnswer as _};
+use hickory_client::rr::rdata::tsig::TsigAlgorithm;
+use hickory_client::rr::rdata::{A, AAAA};
+use hickory_client::rr::{self, DNSClass, Name, RData};
+use hickory_client::udp::UdpClientStream;
+use log::{debug, Level};
+use logging_timer::{finish, stimer};
+use tokio::net::UdpSocket;
+
+use crate::provider::{DnsProvider, Record};
+use crate::{CreateOptions, Error, RecordOptions, RecordType, Result};
+
+const DNS_TIMEOUT: u64 = 10;
+
+/// Maximum difference in seconds between clocks of client and server, as `nsupdate` does
+const TSIG_FUDGE: u16 = 300;
+
+/// Default time to live of DNS records which are updated or created without explicit one
+pub const DEFAULT_RFC2136_TTL: u32 = 300;
+
+fn fqdn(name: &str) -> Result<Name, ProtoError> {
+    let mut name = Name::from_ascii(name)?;
+    name.set_fqdn(true);
+    Ok(name)
+}
+
+/// Fully qualified name of zone or DNS record to query or update
+fn name_of(name: &str) -> Result<Name> {
+    fqdn(name).map_err(|source| Error::InvalidRecord {
+        record: name.to_string(),
+        source: source.into(),
+    })
+}
+
+fn transport<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::Transport {
+        source: Box::new(e),
+    }
+}
+
+/// Classify failure of the server by response code
+fn response_error(zone: &str, code: ResponseCode, message: String) -> Error {
+    match code {
+        ResponseCode::NotAuth | ResponseCode::Refused => Error::Auth { message },
+        ResponseCode::NotZone => Error::ZoneNotFound {
+            zone: zone.to_string(),
+        },
+        ResponseCode::ServFail => Error::ServerFailure { message },
+        _ => Error::Api {
+            status: None,
+            message,
+            retry_after: None,
+        },
+    }
+}
+
+fn rr_type_of(record_type: RecordType) -> rr::RecordType {
+    match record_type {
+        RecordType::A => rr::RecordType::A,
+        RecordType::AAAA => rr::RecordType::AAAA,
+    }
+}
+
+fn rdata_of(ip: IpAddr) -> RData {
+    match ip {
+        IpAddr::V4(ip) => RData::A(A(ip)),
+        IpAddr::V6(ip) => RData::AAAA(AAAA(ip)),
+    }
+}
+
+/// Build an UPDATE message of the zone, with prerequisites and updates
+fn update_message(zone: Name, prerequisites: Vec<rr::Record>, updates: Vec<rr::Record>) -> Message {
+    // for updates, the query section is used for the zone
+    let mut query = Query::new();
+    query
+        .set_name(zone)
+        .set_query_class(DNSClass::IN)
+        .set_query_type(rr::RecordType::SOA);
+
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Update)
+        .set_recursion_desired(false);
+    message.add_zone(query);
+    for prerequisite in prerequisites {
+        message.add_pre_requisite(prerequisite);
+    }
+    message.add_updates(updates);
+    message
+}
+
+/// Authoritative DNS server accepting RFC 2136 dynamic updates signed with TSIG e.g. BIND or Knot
+///
+/// Zone identifier is the zone name and record identifier is the record name,
+/// since DNS has no identifiers of its own. Proxied setting is ignored.
+#[derive(Clone)]
+pub struct Rfc2136Provider {
+    server: SocketAddr,
+    signer: TSigner,
+    ttl: u32,
+}
+
+impl std::fmt::Debug for Rfc2136Provider {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("Rfc2136Provider")
+            .field("server", &self.server)
+            .field("key_name", self.signer.signer_name())
+            .field("algorithm", self.signer.algorithm())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl Rfc2136Provider {
+    /// Creates a [`Rfc2136Provider`] with address of the primary server and TSIG key
+    /// e.g. one generated by `tsig-keygen -a hmac-sha256 <KEY_NAME>`
+    ///
+    /// Algorithm is one of `hmac-sha256`, `hmac-sha384` and `hmac-sha512`,
+    /// and secret is encoded in base64.
+    pub fn new(server: SocketAddr, key_name: &str, algorithm: &str, secret: &str) -> Result<Self> {
+        let invalid = |message: String| Error::InvalidTsigKey { message };
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(secret.trim())
+            .map_err(|e| invalid(format!("secret is not valid base64: {e}")))?;
+        let algorithm = TsigAlgorithm::from_name(
+            Name::from_ascii(algorithm.to_ascii_lowercase())
+                .map_err(|e| invalid(format!("invalid algorithm {algorithm}: {e}")))?,
+        );
+        let key_name =
+            fqdn(key_name).map_err(|e| invalid(format!("invalid key name {key_name}: {e}")))?;
+        let signer = TSigner::new(key, algorithm, key_name, TSIG_FUDGE)
+            .map_err(|e| invalid(format!("unsupported algorithm: {e}")))?;
+        Ok(Self {
+            server,
+            signer,
+            ttl: DEFAULT_RFC2136_TTL,
+        })
+    }
+
+    /// Set time to live of DNS records updated or created without explicit one,
+    /// defaults to [`DEFAULT_RFC2136_TTL`]
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Time to live of DNS records, where 1 (automatic on Cloudflare) means the default one
+    fn ttl_of(&self, ttl: Option<u32>) -> u32 {
+        match ttl {
+            Some(ttl) if ttl > 1 => ttl,
+            _ => self.ttl,
+        }
+    }
+
+    async fn connect(&self) -> Result<AsyncClient> {
+        let stream = UdpClientStream::<UdpSocket, TSigner>::with_timeout_and_signer(
+            self.server,
+            Duration::from_secs(DNS_TIMEOUT),
+            Some(Arc::new(self.signer.clone())),
+        );
+        let (client, background) = AsyncClient::connect(stream).await.map_err(transport)?;
+        tokio::spawn(background);
+        Ok(client)
+    }
+
+    /// Records of the RRset e.g. several addresses of a round-robin name
+    async fn rrset(&self, name: &str, record_type: RecordType) -> Result<Vec<rr::Record>> {
+        let (owner, rr_type) = (name_of(name)?, rr_type_of(record_type));
+        let mut client = self.connect().await?;
+        let res = client
+            .query(owner.clone(), DNSClass::IN, rr_type)
+            .await
+            .map_err(transport)?;
+        match res.response_code() {
+            ResponseCode::NoError | ResponseCode::NXDomain => {}
+            code => {
+                let message = format!("query {name} ({record_type}) failed with {code}");
+                return Err(response_error(name, code, message));
+            }
+        }
+        // answers may hold a CNAME chain, whose target holds the address rather than the record
+        Ok(res
+            .answers()
+            .iter()
+            .filter(|r| r.name() == &owner && r.record_type() == rr_type)
+            .cloned()
+            .collect())
+    }
+
+    async fn query(&self, name: &str, record_type: RecordType) -> Result<Option<Record>> {
+        let server = self.server;
+        let tmr = stimer!(Level::Debug; "QUERY_DNS_RECORD", "server={server},name={name}");
+        let rrset = self.rrset(name, record_type).await?;
+        let record = rrset.iter().find_map(|r| {
+            let ip = match r.data() {
+                Some(RData::A(a)) => IpAddr::V4(a.0),
+                Some(RData::AAAA(aaaa)) => IpAddr::V6(aaaa.0),
+                _ => return None,
+            };
+            Some(Record {
+                id: name.to_string(),
+                name: name.to_string(),
+                content: ip.to_string(),
+                ttl: r.ttl(),
+                proxied: None,
+            })
+        });
+        finish!(tmr, "content={:?}", record.as_ref().map(|r| &r.content));
+        Ok(record)
+    }
+
+    async fn update(&self, zone: &str, message: Message) -> Result<()> {
+        let server = self.server;
+        let tmr = stimer!(Level::Debug; "SEND_DNS_UPDATE", "server={server},zone={zone}");
+        let client = self.connect().await?;
+        let res = client
+            .send(message)
+            .first_answer()
+            .await
+            .map_err(transport)?;
+        match res.response_code() {
+            ResponseCode::NoError => {}
+            code => {
+                let message = format!("update of zone {zone} failed with {code}");
+                return Err(response_error(zone, code, message));
+            }
+        }
+        finish!(tmr);
+        Ok(())
+    }
+
+    async fn replace(&self, zone: &str, name: &str, ip: IpAddr, ttl: u32) -> Result<()> {
+        let (owner, record_type) = (name_of(name)?, RecordType::of(&ip));
+        let rrset = self.rrset(name, record_type).await?;
+        let add = rr::Record::from_rdata(owner.clone(), ttl, rdata_of(ip));
+        let (prerequisites, updates) = match rrset.as_slice() {
+            [] => (vec![], vec![add]),
+            // delete only the record held rather than the RRset, in one message with the addition
+            // and on condition the RRset is still the record, so the update is atomic
+            [held] => match held.data() {
+                Some(data) => {
+                    let unchanged = rr::Record::from_rdata(owner.clone(), 0, data.clone());
+                    let mut delete = unchanged.clone();
+                    delete.set_dns_class(DNSClass::NONE);
+                    (vec![unchanged], vec![delete, add])
+                }
+                None => (vec![], vec![add]),
+            },
+            // which of the addresses to replace is unknown, and replacing all would lose the others
+            held => {
+                return Err(Error::Api {
+                    status: None,
+                    message: format!(
+                        "{name} ({record_type}) holds {} records, refused to replace them with one",
+                        held.len()
+                    ),
+                    retry_after: None,
+                })
+            }
+        };
+        let message = update_message(name_of(zone)?, prerequisites, updates);
+        self.update(zone, message).await
+    }
+
+    async fn create(&self, zone: &str, name: &str, ip: IpAddr, ttl: u32) -> Result<()> {
+        let owner = name_of(name)?;
+        // fail rather than overwrite if the RRset exists in the meantime
+        let mut absent = rr::Record::with(owner.clone(), rr_type_of(RecordType::of(&ip)), 0);
+        absent.set_dns_class(DNSClass::NONE);
+        let add = rr::Record::from_rdata(owner, ttl, rdata_of(ip));
+        let message = update_message(name_of(zone)?, vec![absent], vec![add]);
+        self.update(zone, message).await
+    }
+}
+
+impl DnsProvider for Rfc2136Provider {
+    fn zone_id<'a>(&'a self, zone: &'a str) -> BoxFuture<'a, Result<String>> {
+        async move { Ok(zone.to_string()) }.boxed()
+    }
+
+    fn get_record<'a>(
+        &'a self,
+        _zone_id: &'a str,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> BoxFuture<'a, Result<Option<Record>>> {
+        self.query(name, record_type).boxed()
+    }
+
+    fn create_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: CreateOptions,
+    ) -> BoxFuture<'a, Result<String>> {
+        async move {
+            if options.proxied {
+                debug!("proxied is not supported by RFC 2136, ignored for {name}");
+            }
+            self.create(zone_id, name, ip, self.ttl_of(Some(options.ttl)))
+                .await?;
+            Ok(name.to_string())
+        }
+        .boxed()
+    }
+
+    fn update_record<'a>(
+        &'a self,
+        zone_id: &'a str,
+        _record_id: &'a str,
+        name: &'a str,
+        ip: IpAddr,
+        options: RecordOptions,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            if options.proxied.is_some() {
+                debug!("proxied is not supported by RFC 2136, ignored for {name}");
+            }
+            self.replace(zone_id, name, ip, self.ttl_of(options.ttl))
+                .await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Retry;
+
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use hickory_client::rr::rdata::tsig::{make_tsig_record, message_tbs, TSIG};
+
+    const SECRET: &str = "c2VjcmV0LWtleS1mb3ItY2R1LXRlc3RzLW9ubHkhIQ==";
+
+    type Zone = Arc<Mutex<HashMap<(Name, rr::RecordType), Vec<rr::Record>>>>;
+
+    /// Sign response of an authenticated request, as an authoritative server does
+    fn sign_response(signer: &TSigner, request_mac: &[u8], response: &mut Message) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let pre_tsig = TSIG::new(
+            signer.algorithm().clone(),
+            now.as_secs(),
+            signer.fudge(),
+            vec![],
+            response.id(),
+            0,
+            vec![],
+        );
+        let tbs =
+            message_tbs(Some(request_mac), response, &pre_tsig, signer.signer_name()).unwrap();
+        let mac = signer.sign(&tbs).unwrap();
+        response.add_tsig(make_tsig_record(
+            signer.signer_name().clone(),
+            pre_tsig.set_mac(mac),
+        ));
+    }
+
+    fn handle(signer: &TSigner, zone: &Zone, buf: &[u8]) -> Message {
+        let request = Message::from_vec(buf).unwrap();
+        let mut response =
+            Message::error_msg(request.id(), request.op_code(), ResponseCode::NoError);
+        let mut zone = zone.lock().unwrap();
+        match request.op_code() {
+            OpCode::Query => {
+                let query = &request.queries()[0];
+                response.add_query(query.clone());
+                let cname = zone.get(&(query.name().clone(), rr::RecordType::CNAME));
+                match (zone.get(&(query.name().clone(), query.query_type())), cname) {
+                    (Some(rrset), _) => {
+                        response.add_answers(rrset.clone());
+                    }
+                    // follow CNAME as a resolver does
+                    (None, Some(cname)) => {
+                        response.add_answers(cname.clone());
+                        if let Some(RData::CNAME(target)) = cname[0].data() {
+                            let key = (target.0.clone(), query.query_type());
+                            if let Some(rrset) = zone.get(&key) {
+                                response.add_answers(rrset.clone());
+                            }
+                        }
+                    }
+                    (None, None) => {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+            }
+            OpCode::Update => {
+                let request_mac = match signer.verify_message_byte(None, buf, true) {
+                    Ok((mac, _, _)) => mac,
+                    Err(_) => {
+                        return Message::error_msg(
+                            request.id(),
+                            OpCode::Update,
+                            ResponseCode::NotAuth,
+                        )
+                    }
+                };
+                let data = |rrset: &[rr::Record]| -> Vec<Option<RData>> {
+                    rrset.iter().map(|r| r.data().cloned()).collect()
+                };
+                // RRset does not exist, or is the records of the prerequisites
+                let failed = request.prerequisites().iter().find_map(|r| {
+                    let key = (r.name().clone(), r.record_type());
+                    let held = zone.get(&key).map(|rrset| data(rrset));
+                    match r.dns_class() {
+                        DNSClass::NONE => held.map(|_| ResponseCode::YXRRSet),
+                        _ => {
+                            let expected = request
+                                .prerequisites()
+                                .iter()
+                                .filter(|p| (p.name(), p.record_type()) == (&key.0, key.1));
+                            let expected = expected.map(|p| p.data().cloned()).collect();
+                            (held != Some(expected)).then_some(ResponseCode::NXRRSet)
+                        }
+                    }
+                });
+                if let Some(code) = failed {
+                    response.set_response_code(code);
+                } else {
+                    for update in request.updates() {
+                        let key = (update.name().clone(), update.record_type());
+                        let rrset = zone.entry(key.clone()).or_default();
+                        match update.dns_class() {
+                            DNSClass::ANY => rrset.clear(),
+                            DNSClass::NONE => rrset.retain(|r| r.data() != update.data()),
+                            _ => {
+                                rrset.retain(|r| r.data() != update.data());
+                                rrset.push(update.clone());
+                            }
+                        }
+                        if rrset.is_empty() {
+                            zone.remove(&key);
+                        }
+                    }
+                }
+                sign_response(signer, &request_mac, &mut response);
+            }
+            _ => {
+                response.set_response_code(ResponseCode::NotImp);
+            }
+        }
+        response
+    }
+
+    /// Authoritative server on localhost accepting updates signed with the key
+    async fn serve(signer: TSigner) -> (SocketAddr, Zone) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let zone: Zone = Arc::default();
+        let records = zone.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 4096];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let response = handle(&signer, &records, &buf[..len]);
+                socket
+                    .send_to(&response.to_vec().unwrap(), peer)
+                    .await
+                    .unwrap();
+            }
+        });
+        (addr, zone)
+    }
+
+    fn server_signer() -> TSigner {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(SECRET)
+            .unwrap();
+        TSigner::new(
+            key,
+            TsigAlgorithm::HmacSha256,
+            fqdn("cdu-key").unwrap(),
+            TSIG_FUDGE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn t_new() {
+        let server = "127.0.0.1:53".parse().unwrap();
+        assert!(Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", SECRET).is_ok());
+        assert!(Rfc2136Provider::new(server, "cdu-key", "HMAC-SHA512", SECRET).is_ok());
+        assert!(Rfc2136Provider::new(server, "cdu-key", "hmac-md5", SECRET).is_err());
+        assert!(Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", "not base64!").is_err());
+    }
+
+    #[test]
+    fn t_ttl_of() {
+        let server = "127.0.0.1:53".parse().unwrap();
+        let provider = Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", SECRET)
+            .unwrap()
+            .with_ttl(60);
+        assert_eq!(60, provider.ttl_of(None));
+        assert_eq!(60, provider.ttl_of(Some(1)));
+        assert_eq!(3600, provider.ttl_of(Some(3600)));
+    }
+
+    #[tokio::test]
+    async fn t_rfc2136_provider() {
+        let (server, zone) = serve(server_signer()).await;
+        let provider = Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", SECRET).unwrap();
+
+        let zone_id = provider.zone_id("x.com").await.unwrap();
+        assert_eq!("x.com", zone_id);
+        let record = provider
+            .get_record(&zone_id, "a.x.com", RecordType::A)
+            .await
+            .unwrap();
+        assert!(record.is_none());
+
+        let options = CreateOptions::default();
+        let id = provider
+            .create_record(&zone_id, "a.x.com", "1.1.1.1".parse().unwrap(), options)
+            .await
+            .unwrap();
+        assert_eq!("a.x.com", id);
+        // creation fails rather than overwrites
+        assert!(provider
+            .create_record(&zone_id, "a.x.com", "1.1.1.2".parse().unwrap(), options)
+            .await
+            .is_err());
+
+        let record = provider
+            .get_record(&zone_id, "a.x.com", RecordType::A)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("1.1.1.1", record.content);
+        assert_eq!(DEFAULT_RFC2136_TTL, record.ttl);
+        assert_eq!(None, record.proxied);
+
+        let options = RecordOptions {
+            ttl: Some(60),
+            proxied: None,
+        };
+        provider
+            .update_record(
+                &zone_id,
+                &id,
+                "a.x.com",
+                "1.1.1.2".parse().unwrap(),
+                options,
+            )
+            .await
+            .unwrap();
+        let record = provider
+            .get_record(&zone_id, "a.x.com", RecordType::A)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("1.1.1.2", record.content);
+        assert_eq!(60, record.ttl);
+        assert_eq!(1, zone.lock().unwrap().len());
+
+        provider
+            .update_record(
+                &zone_id,
+                "b.x.com",
+                "b.x.com",
+                "::1".parse().unwrap(),
+                options,
+            )
+            .await
+            .unwrap();
+        let record = provider
+            .get_record(&zone_id, "b.x.com", RecordType::AAAA)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!("::1", record.content);
+
+        // address of CNAME target is not the record
+        let cname = rr::Record::from_rdata(
+            fqdn("c.x.com").unwrap(),
+            300,
+            RData::CNAME(rr::rdata::CNAME(fqdn("a.x.com").unwrap())),
+        );
+        zone.lock()
+            .unwrap()
+            .insert((cname.name().clone(), rr::RecordType::CNAME), vec![cname]);
+        let record = provider
+            .get_record(&zone_id, "c.x.com", RecordType::A)
+            .await
+            .unwrap();
+        assert!(record.is_none());
+    }
+
+    #[tokio::test]
+    async fn t_rfc2136_provider_round_robin() {
+        let (server, zone) = serve(server_signer()).await;
+        let provider = Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", SECRET).unwrap();
+        let owner = fqdn("a.x.com").unwrap();
+        let rrset: Vec<rr::Record> = ["1.1.1.1", "1.1.1.2"]
+            .iter()
+            .map(|ip| rr::Record::from_rdata(owner.clone(), 300, rdata_of(ip.parse().unwrap())))
+            .collect();
+        zone.lock()
+            .unwrap()
+            .insert((owner.clone(), rr::RecordType::A), rrset.clone());
+
+        // replacing would collapse the RRset into one record
+        let e = provider
+            .update_record(
+                "x.com",
+                "a.x.com",
+                "a.x.com",
+                "1.1.1.3".parse().unwrap(),
+                RecordOptions::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "DNS provider fails: a.x.com (A) holds 2 records, refused to replace them with one",
+            e.to_string()
+        );
+        assert!(!e.is_retryable());
+        assert_eq!(
+            Some(&rrset),
+            zone.lock().unwrap().get(&(owner, rr::RecordType::A))
+        );
+    }
+
+    #[tokio::test]
+    async fn t_rfc2136_provider_wrong_key() {
+        let (server, zone) = serve(server_signer()).await;
+        let secret = base64::engine::general_purpose::STANDARD.encode("another key");
+        let provider = Rfc2136Provider::new(server, "cdu-key", "hmac-sha256", &secret).unwrap();
+        let options = RecordOptions::default();
+        assert!(provider
+            .update_record(
+                "x.com",
+                "a.x.com",
+                "a.x.com",
+                "1.1.1.1".parse().unwrap(),
+                options
+            )
+            .await
+            .is_err());
+        assert!(zone.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn t_response_error() {
+        let e = response_error("x.com", ResponseCode::NotAuth, "NOTAUTH".into());
+        assert!(matches!(e, Error::Auth { .. }));
+        assert!(!e.is_retryable());
+        let e = response_error("x.com", ResponseCode::Refused, "REFUSED".into());
+        assert!(matches!(e, Error::Auth { .. }));
+        assert!(!e.is_retryable());
+        let e = response_error("x.com", ResponseCode::NotZone, "NOTZONE".into());
+        assert_eq!("zone not found: x.com", e.to_string());
+        assert!(!e.is_retryable());
+        // transient failure of server, as HTTP 5xx
+        let e = response_error("x.com", ResponseCode::ServFail, "SERVFAIL".into());
+        assert_eq!("DNS server fails: SERVFAIL", e.to_string());
+        assert_eq!(Some(Retry::Backoff), e.retry());
+        let e = response_error("x.com", ResponseCode::FormErr, "FORMERR".into());
+        assert!(matches!(e, Error::Api { status: None, .. }));
+        assert!(!e.is_retryable());
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{RecordChange, RecordType, Retry};
+
+/// Outcome of one DNS record in a run
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordOutcome {
+    /// DNS record did not exist and is created
+    Created,
+    /// DNS record is updated
+    Updated,
+    /// DNS record holds the IP address and settings already
+    Unchanged,
+    /// DNS record cannot be created or updated
+    Failed,
+}
+
+impl Display for RecordOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordOutcome::Created => write!(f, "created"),
+            RecordOutcome::Updated => write!(f, "updated"),
+            RecordOutcome::Unchanged => write!(f, "unchanged"),
+            RecordOutcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Result of one DNS record in a run
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RecordReport {
+    /// DNS record name
+    pub name: String,
+    /// DNS record type
+    #[serde(rename = "type")]
+    pub record_type: RecordType,
+    /// Outcome of DNS record
+    pub outcome: RecordOutcome,
+    /// IP address held before, if known
+    pub previous: Option<IpAddr>,
+    /// IP address to publish
+    pub ip: IpAddr,
+    /// Error with its causes if DNS record fails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// How DNS record may be retried if it fails
+    #[serde(skip)]
+    pub retry: Option<Retry>,
+}
+
+impl RecordReport {
+    /// Change of IP address, if DNS record is created or updated to another IP address
+    pub fn change(&self) -> Option<RecordChange> {
+        let moved = match self.outcome {
+            RecordOutcome::Created => true,
+            RecordOutcome::Updated => self.previous != Some(self.ip),
+            RecordOutcome::Unchanged | RecordOutcome::Failed => false,
+        };
+        moved.then(|| RecordChange {
+            name: self.name.clone(),
+            record_type: self.record_type,
+            previous: self.previous,
+            current: self.ip,
+        })
+    }
+}
+
+impl Display for RecordReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (name, record_type, ip) = (&self.name, self.record_type, self.ip);
+        write!(f, "{name} ({record_type}): ")?;
+        match (self.outcome, self.previous) {
+            (RecordOutcome::Created, _) => write!(f, "created {ip}"),
+            (RecordOutcome::Updated, Some(previous)) => write!(f, "updated {previous} -> {ip}"),
+            (RecordOutcome::Updated, None) => write!(f, "updated to {ip}"),
+            (RecordOutcome::Unchanged, _) => write!(f, "unchanged {ip}"),
+            (RecordOutcome::Failed, _) => {
+                let error = self.error.as_deref().unwrap_or("unknown error");
+                write!(f, "failed to publish {ip}: {error}")
+            }
+        }
+    }
+}
+
+/// Result of a run on DNS records of a zone
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct RunReport {
+    /// Zone name
+    pub zone: String,
+    /// Results of DNS records, empty if IP addresses remain unchanged since last run
+    pub records: Vec<RecordReport>,
+    /// Results of IP lists kept holding the IP addresses, named `list:<NAME>`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lists: Vec<RecordReport>,
+}
+
+/// Take outcomes of reports retried after failing, others keep their outcomes
+fn merge_reports(reports: &mut Vec<RecordReport>, retried: Vec<RecordReport>) {
+    for record in retried {
+        let position = reports
+            .iter()
+            .position(|r| r.name == record.name && r.record_type == record.record_type);
+        match position {
+            Some(i) if reports[i].outcome == RecordOutcome::Failed => reports[i] = record,
+            Some(_) => {}
+            None => reports.push(record),
+        }
+    }
+}
+
+impl RunReport {
+    /// DNS records which change IP address
+    pub fn changes(&self) -> Vec<RecordChange> {
+        self.records
+            .iter()
+            .filter_map(RecordReport::change)
+            .collect()
+    }
+
+    /// DNS records which fail
+    pub fn failures(&self) -> Vec<&RecordReport> {
+        self.records
+            .iter()
+            .filter(|r| r.outcome == RecordOutcome::Failed)
+            .collect()
+    }
+
+    /// IP lists which fail
+    pub fn list_failures(&self) -> Vec<&RecordReport> {
+        self.lists
+            .iter()
+            .filter(|r| r.outcome == RecordOutcome::Failed)
+            .collect()
+    }
+
+    /// Whether no DNS record or IP list fails
+    pub fn is_success(&self) -> bool {
+        self.failures().is_empty() && self.list_failures().is_empty()
+    }
+
+    /// Whether neither DNS records nor IP lists are touched
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.lists.is_empty()
+    }
+
+    /// How failed DNS records and IP lists may be retried, `None` if none of them is retryable
+    pub fn retry(&self) -> Option<Retry> {
+        self.failures()
+            .into_iter()
+            .chain(self.list_failures())
+            .filter_map(|r| r.retry)
+            .reduce(Retry::max)
+    }
+
+    /// Take outcomes of DNS records and IP lists retried after failing,
+    /// others keep their outcomes
+    pub fn merge(mut self, retried: RunReport) -> RunReport {
+        merge_reports(&mut self.records, retried.records);
+        merge_reports(&mut self.lists, retried.lists);
+        self
+    }
+}
+
+impl Display for RunReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "zone {}", self.zone)?;
+        if self.is_empty() {
+            return write!(f, "\n  IP addresses unchanged");
+        }
+        for record in self.records.iter().chain(&self.lists) {
+            write!(f, "\n  {record}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn record() -> RecordReport {
+        RecordReport {
+            name: "record".into(),
+            record_type: RecordType::A,
+            outcome: RecordOutcome::Updated,
+            previous: Some("127.0.0.1".parse().unwrap()),
+            ip: "127.0.0.2".parse().unwrap(),
+            error: None,
+            retry: None,
+        }
+    }
+
+    #[test]
+    fn t_record_change() {
+        let mut record = record();
+        let change = record.change().unwrap();
+        assert_eq!("record (A): 127.0.0.1 -> 127.0.0.2", change.to_string());
+
+        // settings change only
+        record.previous = Some(record.ip);
+        assert!(record.change().is_none());
+
+        record.outcome = RecordOutcome::Unchanged;
+        assert!(record.change().is_none());
+
+        record.outcome = RecordOutcome::Failed;
+        assert!(record.change().is_none());
+
+        record.outcome = RecordOutcome::Created;
+        record.previous = None;
+        let change = record.change().unwrap();
+        assert_eq!("record (A): (unknown) -> 127.0.0.2", change.to_string());
+    }
+
+    #[test]
+    fn t_display_report() {
+        let mut report = RunReport {
+            zone: "x.com".into(),
+            records: vec![],
+            lists: vec![],
+        };
+        assert!(report.is_success());
+        assert_eq!("zone x.com\n  IP addresses unchanged", report.to_string());
+
+        report.records = vec![
+            RecordReport {
+                name: "a.x.com".into(),
+                ..record()
+            },
+            RecordReport {
+                name: "b.x.com".into(),
+                outcome: RecordOutcome::Created,
+                previous: None,
+                ..record()
+            },
+            RecordReport {
+                name: "c.x.com".into(),
+                outcome: RecordOutcome::Unchanged,
+                previous: Some("127.0.0.2".parse().unwrap()),
+                ..record()
+            },
+            RecordReport {
+                name: "d.x.com".into(),
+                outcome: RecordOutcome::Failed,
+                error: Some("DNS record not found: d.x.com (A)".into()),
+                ..record()
+            },
+        ];
+        assert!(!report.is_success());
+        assert_eq!(1, report.failures().len());
+        assert_eq!(2, report.changes().len());
+        assert_eq!(
+            "zone x.com\n  \
+            a.x.com (A): updated 127.0.0.1 -> 127.0.0.2\n  \
+            b.x.com (A): created 127.0.0.2\n  \
+            c.x.com (A): unchanged 127.0.0.2\n  \
+            d.x.com (A): failed to publish 127.0.0.2: DNS record not found: d.x.com (A)",
+            report.to_string()
+        );
+    }
+
+    #[test]
+    fn t_retry_and_merge() {
+        let failed = |name: &str, retry| RecordReport {
+            name: name.into(),
+            outcome: RecordOutcome::Failed,
+            error: Some("error".into()),
+            retry,
+            ..record()
+        };
+        let after = Duration::from_secs(5);
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![
+                record(),
+                failed("b.x.com", Some(Retry::Backoff)),
+                failed("c.x.com", Some(Retry::After(after))),
+                failed("d.x.com", None),
+            ],
+            lists: vec![],
+        };
+        assert_eq!(Some(Retry::After(after)), report.retry());
+
+        let retried = RunReport {
+            zone: "x.com".into(),
+            records: vec![
+                RecordReport {
+                    outcome: RecordOutcome::Unchanged,
+                    ..record()
+                },
+                RecordReport {
+                    name: "b.x.com".into(),
+                    ..record()
+                },
+                failed("c.x.com", Some(Retry::Backoff)),
+                failed("d.x.com", None),
+            ],
+            lists: vec![],
+        };
+        let report = report.merge(retried);
+        let outcomes: Vec<RecordOutcome> = report.records.iter().map(|r| r.outcome).collect();
+        assert_eq!(
+            vec![
+                RecordOutcome::Updated,
+                RecordOutcome::Updated,
+                RecordOutcome::Failed,
+                RecordOutcome::Failed
+            ],
+            outcomes
+        );
+        assert_eq!(Some(Retry::Backoff), report.retry());
+    }
+
+    #[test]
+    fn t_serialize_report() {
+        let report = RunReport {
+            zone: "x.com".into(),
+            records: vec![
+                record(),
+                RecordReport {
+                    outcome: RecordOutcome::Failed,
+                    previous: None,
+                    error: Some("error".into()),
+                    ..record()
+                },
+            ],
+            lists: vec![],
+        };
+        assert_eq!(
+            r#"{"zone":"x.com","records":[{"name":"record","type":"A","outcome":"updated","previous":"127.0.0.1","ip":"127.0.0.2"},{"name":"record","type":"A","outcome":"failed","previous":null,"ip":"127.0.0.2","error":"error"}]}"#,
+            serde_json::to_string(&report).unwrap()
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::{parse_record_name, Error, RecordType};
+
+/// DNS records selected by exact name, or by pattern expanded against DNS records of the zone
+#[derive(Clone, Debug)]
+pub enum RecordSelector {
+    /// Exact name e.g. `a.x.com`
+    Name(String),
+    /// Glob matching names case-insensitively e.g. `glob:*.home.x.com`,
+    /// `*` matches any characters and `?` matches one character
+    Glob(String, Regex),
+    /// Regular expression matching names e.g. `regex:^(a|b)\.x\.com$`
+    Regex(Regex),
+}
+
+/// Convert glob to an anchored, case-insensitive regular expression
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("(?i)^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+impl FromStr for RecordSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |source: regex::Error| Error::InvalidRecord {
+            record: s.to_string(),
+            source: source.into(),
+        };
+        if let Some(glob) = s.strip_prefix("glob:") {
+            let regex = Regex::new(&glob_to_regex(glob)).map_err(invalid)?;
+            Ok(RecordSelector::Glob(glob.to_string(), regex))
+        } else if let Some(pattern) = s.strip_prefix("regex:") {
+            let regex = Regex::new(pattern).map_err(invalid)?;
+            Ok(RecordSelector::Regex(regex))
+        } else {
+            Ok(RecordSelector::Name(s.to_string()))
+        }
+    }
+}
+
+impl Display for RecordSelector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordSelector::Name(name) => write!(f, "{name}"),
+            RecordSelector::Glob(glob, _) => write!(f, "glob:{glob}"),
+            RecordSelector::Regex(regex) => write!(f, "regex:{regex}"),
+        }
+    }
+}
+
+impl RecordSelector {
+    /// Whether DNS record name is selected
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            RecordSelector::Name(n) => n.eq_ignore_ascii_case(name),
+            RecordSelector::Glob(_, regex) | RecordSelector::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Parse record selector with optional record type e.g. `a.x.com`, `glob:*.x.com:AAAA`
+/// or `regex:^(a|b)\.x\.com$`
+pub fn parse_record_selector(s: &str) -> crate::Result<(RecordSelector, Option<RecordType>)> {
+    let (selector, record_type) = parse_record_name(s);
+    Ok((selector.parse()?, record_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_parse_record_selector() {
+        let (selector, t) = parse_record_selector("a.x.com").unwrap();
+        assert!(matches!(selector, RecordSelector::Name(ref n) if n == "a.x.com"));
+        assert_eq!(None, t);
+
+        let (selector, t) = parse_record_selector("glob:*.home.x.com:AAAA").unwrap();
+        assert_eq!("glob:*.home.x.com", selector.to_string());
+        assert_eq!(Some(RecordType::AAAA), t);
+
+        let (selector, t) = parse_record_selector(r"regex:^(a|b)\.x\.com$").unwrap();
+        assert_eq!(r"regex:^(a|b)\.x\.com$", selector.to_string());
+        assert_eq!(None, t);
+
+        let e = parse_record_selector("regex:(:AAAA").unwrap_err();
+        assert_eq!("invalid record regex:(", e.to_string());
+    }
+
+    #[test]
+    fn t_matches() {
+        let selector: RecordSelector = "a.x.com".parse().unwrap();
+        assert!(selector.matches("a.x.com"));
+        assert!(selector.matches("A.x.com"));
+        assert!(!selector.matches("b.x.com"));
+
+        let selector: RecordSelector = "glob:*.home.x.com".parse().unwrap();
+        assert!(selector.matches("a.home.x.com"));
+        assert!(selector.matches("a.b.HOME.x.com"));
+        assert!(!selector.matches("home.x.com"));
+        assert!(!selector.matches("a.homexx.com"));
+
+        let selector: RecordSelector = "glob:?.x.com".parse().unwrap();
+        assert!(selector.matches("a.x.com"));
+        assert!(!selector.matches("ab.x.com"));
+
+        let selector: RecordSelector = r"regex:^(a|b)\.x\.com$".parse().unwrap();
+        assert!(selector.matches("a.x.com"));
+        assert!(selector.matches("b.x.com"));
+        assert!(!selector.matches("c.x.com"));
+    }
+}
@@ -0,0 +1,435 @@
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context as _};
+use futures::future::{join_all, BoxFuture};
+use futures::{FutureExt as _, TryFutureExt as _};
+use log::{debug, warn};
+
+use crate::address::non_public_range;
+use crate::{BoxError, Error, NoQuorum, RecordType};
+
+const SOURCE_TIMEOUT: u64 = 10;
+
+/// Source of IP address to publish, failing with cause of any type e.g. [`NoQuorum`]
+pub trait IpSource: Debug + Send + Sync {
+    /// Fetch IP address of the address family of the record type
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>>;
+}
+
+/// Parse IP source from specification, one of:
+///
+/// * `public` for public IP resolvers e.g. ipify.org
+/// * `url=<URL>` for URL returning IP address in plain text or JSON e.g. `{"ip":"1.1.1.1"}`
+/// * `interface=<NAME>` for IP address on local network interface e.g. `interface=eth0`
+/// * `command=<COMMAND>` for IP address printed by shell command, with record type in `CDU_RECORD_TYPE`
+pub fn parse_ip_source(spec: &str) -> crate::Result<Arc<dyn IpSource>> {
+    let (kind, value) = match spec.split_once('=') {
+        Some((kind, value)) => (kind, Some(value)),
+        None => (spec, None),
+    };
+    match (kind, value) {
+        ("public", None) => Ok(Arc::new(PublicIpSource)),
+        ("url", Some(url)) => Ok(Arc::new(HttpIpSource::new(url))),
+        ("interface", Some(name)) => Ok(Arc::new(InterfaceIpSource::new(name))),
+        ("command", Some(command)) => Ok(Arc::new(CommandIpSource::new(command))),
+        _ => Err(Error::InvalidIpSource {
+            spec: spec.to_string(),
+        }),
+    }
+}
+
+/// Find the first IP address of the address family of the record type in text
+fn find_ip<'a, I>(candidates: I, record_type: RecordType) -> Option<IpAddr>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .filter_map(|s| s.trim().parse::<IpAddr>().ok())
+        .find(|ip| RecordType::of(ip) == record_type)
+}
+
+/// Public IP resolvers e.g. ipify.org
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PublicIpSource;
+
+impl IpSource for PublicIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let ip = match record_type {
+                RecordType::A => public_ip::addr_v4().await.map(IpAddr::V4),
+                RecordType::AAAA => public_ip::addr_v6().await.map(IpAddr::V6),
+            };
+            ip.context("no answer from public IP resolvers")
+        }
+        .map_err(BoxError::from)
+        .boxed()
+    }
+}
+
+/// URL returning IP address in plain text or JSON e.g. `{"ip":"1.1.1.1"}`
+#[derive(Clone, Debug)]
+pub struct HttpIpSource {
+    url: String,
+    client: reqwest::Client,
+    timeout: Duration,
+}
+
+impl HttpIpSource {
+    /// Creates a [`HttpIpSource`]
+    pub fn new<T: Into<String>>(url: T) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+            timeout: Duration::from_secs(SOURCE_TIMEOUT),
+        }
+    }
+
+    /// Set HTTP client, e.g. one shared with other sources to reuse connections
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Set timeout of each request, defaults to 10 seconds
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl IpSource for HttpIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let body = self
+                .client
+                .get(&self.url)
+                .timeout(self.timeout)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let ip = match serde_json::from_str::<serde_json::Value>(&body) {
+                Ok(serde_json::Value::Object(o)) => {
+                    find_ip(o.values().filter_map(|v| v.as_str()), record_type)
+                }
+                Ok(serde_json::Value::String(s)) => find_ip(Some(s.as_str()), record_type),
+                _ => find_ip(body.lines(), record_type),
+            };
+            let url = &self.url;
+            ip.with_context(|| format!("no {record_type} address found in response of {url}"))
+        }
+        .map_err(BoxError::from)
+        .boxed()
+    }
+}
+
+/// IP address on local network interface
+#[derive(Clone, Debug)]
+pub struct InterfaceIpSource {
+    name: String,
+}
+
+impl InterfaceIpSource {
+    /// Creates a [`InterfaceIpSource`] with name of network interface e.g. `eth0`
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+/// Whether IP address is reachable from outside the link
+fn is_global_unicast(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_link_local(),
+        // fe80::/10 is link-local
+        IpAddr::V6(ip) => !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+/// IP address of the address family of the record type among addresses of network interface,
+/// preferring the one which may be published in public DNS over e.g. private or unique local
+/// one, which is taken only if allowed explicitly
+fn interface_ip<I>(ips: I, record_type: RecordType) -> Option<IpAddr>
+where
+    I: IntoIterator<Item = IpAddr>,
+{
+    let candidates: Vec<IpAddr> = ips
+        .into_iter()
+        .filter(|ip| RecordType::of(ip) == record_type && is_global_unicast(ip))
+        .collect();
+    candidates
+        .iter()
+        .find(|ip| non_public_range(ip).is_none())
+        .or_else(|| candidates.first())
+        .copied()
+}
+
+impl IpSource for InterfaceIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let name = &self.name;
+            let ips = if_addrs::get_if_addrs()?
+                .into_iter()
+                .filter(|i| &i.name == name)
+                .map(|i| i.ip());
+            let ip = interface_ip(ips, record_type);
+            ip.with_context(|| format!("no {record_type} address found on interface {name}"))
+        }
+        .map_err(BoxError::from)
+        .boxed()
+    }
+}
+
+/// IP address printed by shell command, with record type in `CDU_RECORD_TYPE`
+#[derive(Clone, Debug)]
+pub struct CommandIpSource {
+    command: String,
+}
+
+impl CommandIpSource {
+    /// Creates a [`CommandIpSource`]
+    pub fn new<T: Into<String>>(command: T) -> Self {
+        Self {
+            command: command.into(),
+        }
+    }
+}
+
+impl IpSource for CommandIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let command = &self.command;
+            let child = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("CDU_RECORD_TYPE", record_type.to_string())
+                .kill_on_drop(true)
+                .output();
+            let output = tokio::time::timeout(Duration::from_secs(SOURCE_TIMEOUT), child)
+                .await
+                .with_context(|| format!("command timed out: {command}"))??;
+            if !output.status.success() {
+                bail!("command exited with {}: {command}", output.status);
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            find_ip(stdout.lines(), record_type)
+                .with_context(|| format!("no {record_type} address found in output of {command}"))
+        }
+        .map_err(BoxError::from)
+        .boxed()
+    }
+}
+
+/// Fixed IP address e.g. to publish an IP address held before
+#[derive(Clone, Copy, Debug)]
+pub struct StaticIpSource {
+    ip: IpAddr,
+}
+
+impl StaticIpSource {
+    /// Creates a [`StaticIpSource`]
+    pub fn new(ip: IpAddr) -> Self {
+        Self { ip }
+    }
+}
+
+impl IpSource for StaticIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let ip = self.ip;
+            if RecordType::of(&ip) != record_type {
+                bail!("{ip} is not a {record_type} address");
+            }
+            Ok(ip)
+        }
+        .map_err(BoxError::from)
+        .boxed()
+    }
+}
+
+/// IP address agreed by a quorum of sources, which are queried concurrently
+#[derive(Debug)]
+pub struct QuorumIpSource {
+    sources: Vec<Arc<dyn IpSource>>,
+    quorum: usize,
+}
+
+impl QuorumIpSource {
+    /// Creates a [`QuorumIpSource`], quorum must be between 1 and number of sources
+    pub fn new(sources: Vec<Arc<dyn IpSource>>, quorum: usize) -> crate::Result<Self> {
+        let len = sources.len();
+        if quorum == 0 || quorum > len {
+            return Err(Error::InvalidQuorum {
+                quorum,
+                sources: len,
+            });
+        }
+        Ok(Self { sources, quorum })
+    }
+}
+
+impl IpSource for QuorumIpSource {
+    fn fetch(&self, record_type: RecordType) -> BoxFuture<'_, Result<IpAddr, BoxError>> {
+        async move {
+            let fetches = self.sources.iter().map(|s| s.fetch(record_type));
+            let mut votes: Vec<(IpAddr, usize)> = vec![];
+            let mut failures = 0;
+            for (source, res) in self.sources.iter().zip(join_all(fetches).await) {
+                match res {
+                    Ok(ip) => {
+                        debug!("{source:?} answers {ip}");
+                        match votes.iter_mut().find(|(i, _)| *i == ip) {
+                            Some((_, n)) => *n += 1,
+                            None => votes.push((ip, 1)),
+                        }
+                    }
+                    Err(e) => {
+                        warn!("{source:?} fails to answer: {e:#}");
+                        failures += 1;
+                    }
+                }
+            }
+            match votes.iter().find(|(_, n)| *n >= self.quorum) {
+                Some((ip, _)) => Ok(*ip),
+                None => Err(NoQuorum {
+                    quorum: self.quorum,
+                    votes,
+                    failures,
+                }
+                .into()),
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use mockito::mock;
+
+    #[test]
+    fn t_parse_ip_source() {
+        assert!(parse_ip_source("public").is_ok());
+        assert!(parse_ip_source("url=https://api.ipify.org?format=json").is_ok());
+        assert!(parse_ip_source("interface=eth0").is_ok());
+        assert!(parse_ip_source("command=echo 127.0.0.1").is_ok());
+        assert!(parse_ip_source("url").is_err());
+        assert!(parse_ip_source("unknown=1").is_err());
+    }
+
+    #[tokio::test]
+    async fn t_http_ip_source() {
+        let _m1 = mock("GET", "/text")
+            .with_status(200)
+            .with_body("127.0.0.1\n")
+            .create();
+        let _m2 = mock("GET", "/json")
+            .with_status(200)
+            .with_body(r#"{"ip":"::1"}"#)
+            .create();
+
+        let source = HttpIpSource::new(format!("{}/text", mockito::server_url()));
+        let ip = source.fetch(RecordType::A).await.unwrap();
+        assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), ip);
+        assert!(source.fetch(RecordType::AAAA).await.is_err());
+
+        let source = HttpIpSource::new(format!("{}/json", mockito::server_url()));
+        let ip = source.fetch(RecordType::AAAA).await.unwrap();
+        assert_eq!("::1".parse::<IpAddr>().unwrap(), ip);
+    }
+
+    #[tokio::test]
+    async fn t_command_ip_source() {
+        let source = CommandIpSource::new(
+            r#"if [ "$CDU_RECORD_TYPE" = A ]; then echo 127.0.0.1; else echo ::1; fi"#,
+        );
+        let ip = source.fetch(RecordType::A).await.unwrap();
+        assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), ip);
+        let ip = source.fetch(RecordType::AAAA).await.unwrap();
+        assert_eq!("::1".parse::<IpAddr>().unwrap(), ip);
+
+        let source = CommandIpSource::new("exit 1");
+        assert!(source.fetch(RecordType::A).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn t_static_ip_source() {
+        let source = StaticIpSource::new("127.0.0.1".parse().unwrap());
+        let ip = source.fetch(RecordType::A).await.unwrap();
+        assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), ip);
+        let e = source.fetch(RecordType::AAAA).await.unwrap_err();
+        assert_eq!("127.0.0.1 is not a AAAA address", e.to_string());
+    }
+
+    #[test]
+    fn t_interface_ip() {
+        let ips =
+            |ips: &[&str]| -> Vec<IpAddr> { ips.iter().map(|i| i.parse().unwrap()).collect() };
+        let cases = [
+            (
+                &["fe80::1", "fd00::1", "2606:4700::1"][..],
+                RecordType::AAAA,
+                Some("2606:4700::1"),
+            ),
+            (&["192.168.1.2", "1.1.1.1"], RecordType::A, Some("1.1.1.1")),
+            (
+                &["1.1.1.1", "2606:4700::1"],
+                RecordType::AAAA,
+                Some("2606:4700::1"),
+            ),
+            // private address is taken only without public one, so it can be allowed explicitly
+            (&["127.0.0.1", "10.0.0.1"], RecordType::A, Some("10.0.0.1")),
+            (&["::1", "fe80::1"], RecordType::AAAA, None),
+        ];
+        for (candidates, record_type, expected) in cases {
+            assert_eq!(
+                expected.map(|i| i.parse::<IpAddr>().unwrap()),
+                interface_ip(ips(candidates), record_type),
+                "{candidates:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn t_interface_ip_source() {
+        // loopback address is not reachable from outside
+        let source = InterfaceIpSource::new("lo");
+        assert!(source.fetch(RecordType::A).await.is_err());
+        let source = InterfaceIpSource::new("cdu-nonexistent");
+        assert!(source.fetch(RecordType::A).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn t_quorum_ip_source() {
+        let sources: Vec<Arc<dyn IpSource>> = vec![
+            Arc::new(CommandIpSource::new("echo 127.0.0.1")),
+            Arc::new(CommandIpSource::new("echo 127.0.0.1")),
+            Arc::new(CommandIpSource::new("echo 127.0.0.2")),
+            Arc::new(CommandIpSource::new("exit 1")),
+        ];
+        assert!(QuorumIpSource::new(sources.clone(), 0).is_err());
+        assert!(QuorumIpSource::new(sources.clone(), 5).is_err());
+
+        let source = QuorumIpSource::new(sources.clone(), 2).unwrap();
+        let ip = source.fetch(RecordType::A).await.unwrap();
+        assert_eq!("127.0.0.1".parse::<IpAddr>().unwrap(), ip);
+
+        let source = QuorumIpSource::new(sources, 3).unwrap();
+        let e = source.fetch(RecordType::A).await.unwrap_err();
+        let no_quorum = e.downcast_ref::<NoQuorum>().unwrap();
+        assert_eq!(1, no_quorum.failures);
+        assert_eq!(
+            vec![
+                ("127.0.0.1".parse::<IpAddr>().unwrap(), 2),
+                ("127.0.0.2".parse::<IpAddr>().unwrap(), 1)
+            ],
+            no_quorum.votes
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+
+use crate::RecordType;
+
+/// Last published state of one DNS record
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct RecordState {
+    pub(crate) name: String,
+    #[serde(rename = "type")]
+    pub(crate) record_type: RecordType,
+    pub(crate) id: String,
+    pub(crate) ip: IpAddr,
+}
+
+/// State persisted across restarts
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct State {
+    pub(crate) zone: String,
+    pub(crate) zone_id: Option<String>,
+    /// IP address last published to all DNS records of the record type
+    #[serde(default)]
+    pub(crate) last_ips: BTreeMap<RecordType, IpAddr>,
+    #[serde(default)]
+    pub(crate) records: Vec<RecordState>,
+}
+
+impl State {
+    /// Load state from file, returns empty state if file does not exist
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid state file: {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read state file: {}", path.display())),
+        }
+    }
+
+    /// Save state to file atomically by writing a temporary file then renaming it
+    pub(crate) fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let mut tmp_path = PathBuf::from(path);
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".tmp");
+        tmp_path.set_file_name(file_name);
+
+        let mut file = fs::File::create(&tmp_path)
+            .with_context(|| format!("cannot write state file: {}", tmp_path.display()))?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("cannot write state file: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub(crate) fn record(&self, name: &str, record_type: RecordType) -> Option<&RecordState> {
+        self.records
+            .iter()
+            .find(|r| r.name == name && r.record_type == record_type)
+    }
+
+    pub(crate) fn upsert_record(&mut self, record: RecordState) {
+        self.records
+            .retain(|r| !(r.name == record.name && r.record_type == record.record_type));
+        self.records.push(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = State::load(dir.path().join("state.json")).unwrap();
+        assert_eq!(State::default(), state);
+    }
+
+    #[test]
+    fn t_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let mut state = State {
+            zone: "zone".into(),
+            zone_id: Some("1".into()),
+            last_ips: BTreeMap::from([(RecordType::A, "127.0.0.2".parse().unwrap())]),
+            records: vec![],
+        };
+        state.upsert_record(RecordState {
+            name: "record".into(),
+            record_type: RecordType::A,
+            id: "2".into(),
+            ip: "127.0.0.1".parse().unwrap(),
+        });
+        state.upsert_record(RecordState {
+            name: "record".into(),
+            record_type: RecordType::A,
+            id: "2".into(),
+            ip: "127.0.0.2".parse().unwrap(),
+        });
+        state.save(&path).unwrap();
+        assert!(!dir.path().join("state.json.tmp").exists());
+
+        let loaded = State::load(&path).unwrap();
+        assert_eq!(state, loaded);
+        assert_eq!(1, loaded.records.len());
+        let record = loaded.record("record", RecordType::A).unwrap();
+        assert_eq!("127.0.0.2".parse::<IpAddr>().unwrap(), record.ip);
+        assert!(loaded.record("record", RecordType::AAAA).is_none());
+
+        // state saved before last IP addresses are kept
+        fs::write(&path, r#"{"zone":"zone","zone_id":"1"}"#).unwrap();
+        assert!(State::load(&path).unwrap().last_ips.is_empty());
+    }
+}
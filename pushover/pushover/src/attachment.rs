@@ -100,7 +100,7 @@ impl<'a> Attachment<'a> {
         let parsed = Url::parse(url.as_ref())?;
         let filename = parsed
             .path_segments()
-            .map_or("untitled", |s| s.last().map_or("untitled", |s| s));
+            .map_or("untitled", |mut s| s.next_back().map_or("untitled", |s| s));
         let res = ureq::get(parsed.as_str())
             .call()
             .map_err(|e| AttachmentError::UReq(Box::new(e)))?;